@@ -0,0 +1,216 @@
+//! Non-blocking client built on futures
+//!
+//! hyper 0.7 only offers blocking IO, so `AsyncStockfighter` runs each REST call of a shared
+//! `Stockfighter` on a `CpuPool` and hands back a future. The futures run on any executor,
+//! including a tokio reactor. The WebSocket feeds are exposed as `Stream`s, and are connected
+//! on the pool too, so opening one hands back a future of the stream.
+
+use futures::{future, Async, Future, Poll, Stream};
+use futures::sync::mpsc::{self, UnboundedReceiver};
+use futures_cpupool::CpuPool;
+
+use {Stockfighter, StockfighterError, Result};
+use {IntoChecked, Venue, Symbol, Account, OrderId, Price, Qty};
+use {Quote, StockList, OrderbookList, OrderStatus, StockOrdersStatuses, OrderDirection, OrderType};
+use {TickerTapeQuote, ExecutionOrdersStatuses, StreamEvent, SubscriptionHandle};
+
+// Validate an argument up front, so a bad one fails the future without touching the pool
+macro_rules! checked {
+    ($arg:expr) => (match $arg.into_checked() {
+        Ok(arg) => arg,
+        Err(err) => return Box::new(future::err(err)),
+    })
+}
+
+pub type StockfighterFuture<T> = Box<Future<Item = T, Error = StockfighterError> + Send>;
+
+/// A WebSocket feed as a `Stream` of `StreamEvent`s. The stream ends when the feed gives up
+/// reconnecting, and dropping it closes the feed.
+pub struct FeedStream<T> {
+    rx: UnboundedReceiver<StreamEvent<T>>,
+    // The feed's callback owns the sender, so the stream ends when the feed's thread does
+    _handle: SubscriptionHandle<()>,
+}
+
+impl<T> Stream for FeedStream<T> {
+    type Item = StreamEvent<T>;
+    type Error = StockfighterError;
+
+    fn poll(&mut self) -> Poll<Option<StreamEvent<T>>, StockfighterError> {
+        match self.rx.poll() {
+            Ok(ready) => Ok(ready),
+            Err(()) => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+/// Mirrors every `Stockfighter` method, returning futures and streams instead of blocking
+///
+/// # Example
+///
+/// ```no_run
+/// extern crate futures;
+/// extern crate stockfighter;
+///
+/// use futures::Future;
+/// use stockfighter::AsyncStockfighter;
+///
+/// fn main() {
+///     let sf = AsyncStockfighter::new("fake api key");
+///     let quote = sf.quote("TESTEX", "FOOBAR");
+///     let book = sf.orderbook_for_stock("TESTEX", "FOOBAR");
+///     let (quote, book) = quote.join(book).wait().unwrap();
+///     println!("{:?} {:?}", quote, book);
+/// }
+/// ```
+pub struct AsyncStockfighter {
+    sf: Stockfighter,
+    pool: CpuPool,
+}
+
+impl AsyncStockfighter {
+
+    pub fn new<S>(api_key: S) -> AsyncStockfighter where S: Into<String> {
+        AsyncStockfighter::from_blocking(Stockfighter::new(api_key))
+    }
+
+    /// Wrap an already configured blocking client, e.g. one made with `StockfighterBuilder`
+    pub fn from_blocking(sf: Stockfighter) -> AsyncStockfighter {
+        AsyncStockfighter::with_pool(sf, CpuPool::new_num_cpus())
+    }
+
+    /// Wrap a blocking client and run its calls on `pool`
+    pub fn with_pool(sf: Stockfighter, pool: CpuPool) -> AsyncStockfighter {
+        AsyncStockfighter { sf: sf, pool: pool }
+    }
+
+    fn spawn<T, F>(&self, f: F) -> StockfighterFuture<T>
+        where T: Send + 'static, F: FnOnce(&Stockfighter) -> Result<T> + Send + 'static {
+
+        let sf = self.sf.clone();
+        Box::new(self.pool.spawn_fn(move || f(&sf)))
+    }
+
+    pub fn heartbeat(&self) -> StockfighterFuture<()> {
+        self.spawn(|sf| sf.heartbeat())
+    }
+
+    pub fn venue_heartbeat<V>(&self, venue: V) -> StockfighterFuture<()> where V: IntoChecked<Venue> {
+        let venue = checked!(venue);
+        self.spawn(move |sf| sf.venue_heartbeat(venue))
+    }
+
+    pub fn quote<V, S>(&self, venue: V, stock: S) -> StockfighterFuture<Quote>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let (venue, stock) = (checked!(venue), checked!(stock));
+        self.spawn(move |sf| sf.quote(venue, stock))
+    }
+
+    pub fn stocks_on_a_venue<V>(&self, venue: V) -> StockfighterFuture<StockList> where V: IntoChecked<Venue> {
+        let venue = checked!(venue);
+        self.spawn(move |sf| sf.stocks_on_a_venue(venue))
+    }
+
+    pub fn orderbook_for_stock<V, S>(&self, venue: V, symbol: S) -> StockfighterFuture<OrderbookList>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let (venue, symbol) = (checked!(venue), checked!(symbol));
+        self.spawn(move |sf| sf.orderbook_for_stock(venue, symbol))
+    }
+
+    pub fn new_order<A, V, S, P, Q>(&self, account: A, venue: V, stock: S, price: P, qty: Q,
+                                    direction: OrderDirection, order_type: OrderType)
+                                    -> StockfighterFuture<OrderStatus>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              P: IntoChecked<Price>, Q: IntoChecked<Qty> {
+
+        let (account, venue, stock) = (checked!(account), checked!(venue), checked!(stock));
+        let (price, qty) = (checked!(price), checked!(qty));
+        self.spawn(move |sf| sf.new_order(account, venue, stock, price, qty, direction, order_type))
+    }
+
+    pub fn existing_order_status<I, V, S>(&self, id: I, venue: V, stock: S) -> StockfighterFuture<OrderStatus>
+        where I: IntoChecked<OrderId>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let (id, venue, stock) = (checked!(id), checked!(venue), checked!(stock));
+        self.spawn(move |sf| sf.existing_order_status(id, venue, stock))
+    }
+
+    pub fn status_for_all_orders<V, A>(&self, venue: V, account: A) -> StockfighterFuture<StockOrdersStatuses>
+        where V: IntoChecked<Venue>, A: IntoChecked<Account> {
+
+        let (venue, account) = (checked!(venue), checked!(account));
+        self.spawn(move |sf| sf.status_for_all_orders(venue, account))
+    }
+
+    pub fn status_for_all_orders_on_a_stock<V, A, S>(&self, venue: V, account: A, stock: S)
+                                                     -> StockfighterFuture<StockOrdersStatuses>
+        where V: IntoChecked<Venue>, A: IntoChecked<Account>, S: IntoChecked<Symbol> {
+
+        let (venue, account, stock) = (checked!(venue), checked!(account), checked!(stock));
+        self.spawn(move |sf| sf.status_for_all_orders_on_a_stock(venue, account, stock))
+    }
+
+    pub fn cancel_an_order<V, S, I>(&self, venue: V, stock: S, order: I) -> StockfighterFuture<OrderStatus>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol>, I: IntoChecked<OrderId> {
+
+        let (venue, stock, order) = (checked!(venue), checked!(stock), checked!(order));
+        self.spawn(move |sf| sf.cancel_an_order(venue, stock, order))
+    }
+
+    pub fn ticker_tape_venue<A, V>(&self, account: A, venue: V) -> StockfighterFuture<FeedStream<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
+
+        let (account, venue) = (checked!(account), checked!(venue));
+        self.spawn(move |sf| {
+            let (tx, rx) = mpsc::unbounded();
+            let handle = try!(sf.ticker_tape_venue_with_state(account, venue, (), move |_, event| {
+                let _ = tx.unbounded_send(event);
+            }));
+            Ok(FeedStream { rx: rx, _handle: handle })
+        })
+    }
+
+    pub fn ticker_tape_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S)
+                                            -> StockfighterFuture<FeedStream<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let (account, venue, stock) = (checked!(account), checked!(venue), checked!(stock));
+        self.spawn(move |sf| {
+            let (tx, rx) = mpsc::unbounded();
+            let handle = try!(sf.ticker_tape_venue_stock_with_state(account, venue, stock, (), move |_, event| {
+                let _ = tx.unbounded_send(event);
+            }));
+            Ok(FeedStream { rx: rx, _handle: handle })
+        })
+    }
+
+    pub fn executions_venue<A, V>(&self, account: A, venue: V)
+                                  -> StockfighterFuture<FeedStream<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
+
+        let (account, venue) = (checked!(account), checked!(venue));
+        self.spawn(move |sf| {
+            let (tx, rx) = mpsc::unbounded();
+            let handle = try!(sf.executions_venue_with_state(account, venue, (), move |_, event| {
+                let _ = tx.unbounded_send(event);
+            }));
+            Ok(FeedStream { rx: rx, _handle: handle })
+        })
+    }
+
+    pub fn executions_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S)
+                                           -> StockfighterFuture<FeedStream<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let (account, venue, stock) = (checked!(account), checked!(venue), checked!(stock));
+        self.spawn(move |sf| {
+            let (tx, rx) = mpsc::unbounded();
+            let handle = try!(sf.executions_venue_stock_with_state(account, venue, stock, (), move |_, event| {
+                let _ = tx.unbounded_send(event);
+            }));
+            Ok(FeedStream { rx: rx, _handle: handle })
+        })
+    }
+}
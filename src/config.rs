@@ -0,0 +1,211 @@
+//! Loading the API key and defaults from the environment or a config file
+//!
+//! A config file is TOML with one table per profile:
+//!
+//! ```toml
+//! [default]
+//! api_key = "0123456789abcdef"
+//! account = "EXB123456"
+//! venue = "TESTEX"
+//!
+//! [staging]
+//! api_key = "fedcba9876543210"
+//! base_url = "https://staging.example.com/ob/api"
+//! ws_url = "wss://staging.example.com/ob/api/ws"
+//! gm_url = "https://staging.example.com/gm"
+//!
+//! [local]
+//! api_key = "fedcba9876543210"
+//! base_url = "http://localhost:8000/ob/api"
+//! allow_plaintext = true
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use {Result, StockfighterError, Stockfighter, StockfighterBuilder, Account, Venue, check_url};
+
+/// Environment variable holding the API key
+pub const ENV_API_KEY: &'static str = "STOCKFIGHTER_API_KEY";
+/// Environment variable holding the default account, optional
+pub const ENV_ACCOUNT: &'static str = "STOCKFIGHTER_ACCOUNT";
+/// Environment variable holding the default venue, optional
+pub const ENV_VENUE: &'static str = "STOCKFIGHTER_VENUE";
+/// Environment variable overriding the REST base URL, optional
+pub const ENV_BASE_URL: &'static str = "STOCKFIGHTER_BASE_URL";
+/// Environment variable overriding the WebSocket base URL, optional
+pub const ENV_WS_URL: &'static str = "STOCKFIGHTER_WS_URL";
+/// Environment variable overriding the gamemaster base URL, optional
+pub const ENV_GM_URL: &'static str = "STOCKFIGHTER_GM_URL";
+/// Environment variable allowing `http` and `ws` URLs anywhere when `true` or `1`, optional
+pub const ENV_ALLOW_PLAINTEXT: &'static str = "STOCKFIGHTER_ALLOW_PLAINTEXT";
+
+/// Profile used by `Stockfighter::from_config`
+pub const DEFAULT_PROFILE: &'static str = "default";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    api_key: String,
+    account: Option<String>,
+    venue: Option<String>,
+    base_url: Option<String>,
+    ws_url: Option<String>,
+    gm_url: Option<String>,
+    #[serde(default)]
+    allow_plaintext: bool,
+}
+
+/// `~/.stockfighter.toml`, if the home directory is known
+pub fn default_config_path() -> Option<PathBuf> {
+    env::home_dir().map(|home| home.join(".stockfighter.toml"))
+}
+
+fn config_error<S>(message: S) -> StockfighterError where S: Into<String> {
+    StockfighterError::Config(message.into())
+}
+
+fn var(name: &str) -> Result<Option<String>> {
+    match env::var(name) {
+        Ok(ref value) if value.is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(env::VarError::NotUnicode(_)) => Err(config_error(format!("{} is not valid unicode", name))),
+    }
+}
+
+fn flag(name: &str) -> Result<bool> {
+    match try!(var(name)) {
+        None => Ok(false),
+        Some(ref value) if value == "true" || value == "1" => Ok(true),
+        Some(ref value) if value == "false" || value == "0" => Ok(false),
+        Some(value) => Err(config_error(format!("{} is not true or false: {}", name, value))),
+    }
+}
+
+// Check the defaults here, so a bad one is reported with where it came from. Whether a plaintext
+// URL is allowed is left to `build`, as `allow_plaintext` may still be set on the builder.
+fn with_defaults(profile: Profile, source: &str) -> Result<StockfighterBuilder> {
+    let mut builder = StockfighterBuilder::new(profile.api_key).allow_plaintext(profile.allow_plaintext);
+
+    if let Some(url) = profile.base_url {
+        try!(check_url(&url, "https", "http", true)
+             .map_err(|_| config_error(format!("{}: invalid base URL {}", source, url))));
+        builder = builder.base_url(url);
+    }
+    if let Some(url) = profile.ws_url {
+        try!(check_url(&url, "wss", "ws", true)
+             .map_err(|_| config_error(format!("{}: invalid WebSocket URL {}", source, url))));
+        builder = builder.ws_url(url);
+    }
+    if let Some(url) = profile.gm_url {
+        try!(check_url(&url, "https", "http", true)
+             .map_err(|_| config_error(format!("{}: invalid gamemaster URL {}", source, url))));
+        builder = builder.gm_url(url);
+    }
+    if let Some(account) = profile.account {
+        try!(Account::new(&account[..]).map_err(|err| config_error(format!("{}: {}", source, err))));
+        builder = builder.default_account(account);
+    }
+    if let Some(venue) = profile.venue {
+        try!(Venue::new(&venue[..]).map_err(|err| config_error(format!("{}: {}", source, err))));
+        builder = builder.default_venue(venue);
+    }
+    Ok(builder)
+}
+
+// The URLs are well-formed by now, so a URL `build` refuses is a plaintext one. Say where it came
+// from and which `setting` allows it.
+fn build(builder: StockfighterBuilder, source: &str, setting: &str) -> Result<Stockfighter> {
+    builder.build().map_err(|err| match err {
+        StockfighterError::InvalidUrl(url) =>
+            config_error(format!("{}: plaintext URL {} is only allowed with {}", source, url, setting)),
+        err => err,
+    })
+}
+
+impl StockfighterBuilder {
+
+    /// Builder with the API key and defaults from `STOCKFIGHTER_API_KEY`, `STOCKFIGHTER_ACCOUNT`,
+    /// `STOCKFIGHTER_VENUE`, `STOCKFIGHTER_BASE_URL`, `STOCKFIGHTER_WS_URL`, `STOCKFIGHTER_GM_URL`
+    /// and `STOCKFIGHTER_ALLOW_PLAINTEXT`. Only the API key is required.
+    pub fn from_env() -> Result<StockfighterBuilder> {
+        let api_key = match try!(var(ENV_API_KEY)) {
+            Some(api_key) => api_key,
+            None => return Err(config_error(format!("{} is not set", ENV_API_KEY))),
+        };
+
+        let profile = Profile {
+            api_key: api_key,
+            account: try!(var(ENV_ACCOUNT)),
+            venue: try!(var(ENV_VENUE)),
+            base_url: try!(var(ENV_BASE_URL)),
+            ws_url: try!(var(ENV_WS_URL)),
+            gm_url: try!(var(ENV_GM_URL)),
+            allow_plaintext: try!(flag(ENV_ALLOW_PLAINTEXT)),
+        };
+        with_defaults(profile, "environment")
+    }
+
+    /// Builder with the API key and defaults from `profile` in the TOML file at `path`
+    pub fn from_config<P>(path: P, profile: &str) -> Result<StockfighterBuilder> where P: AsRef<Path> {
+        let path = path.as_ref();
+
+        let mut contents = String::new();
+        try!(File::open(path)
+             .and_then(|mut file| file.read_to_string(&mut contents))
+             .map_err(|err| config_error(format!("cannot read {}: {}", path.display(), err))));
+
+        let mut profiles: BTreeMap<String, Profile> = try!(toml::from_str(&contents)
+            .map_err(|err| config_error(format!("{}: {}", path.display(), err))));
+
+        let profile = match profiles.remove(profile) {
+            Some(found) => found,
+            None => return Err(config_error(format!("{}: no profile [{}]", path.display(), profile))),
+        };
+
+        with_defaults(profile, &path.display().to_string())
+    }
+}
+
+impl Stockfighter {
+
+    /// Client configured from the environment, see `StockfighterBuilder::from_env`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stockfighter::Stockfighter;
+    ///
+    /// let sf = Stockfighter::from_env().unwrap();
+    /// let venue = sf.default_venue().expect("STOCKFIGHTER_VENUE is set");
+    /// let quote = sf.quote(venue, "FOOBAR");
+    /// ```
+    pub fn from_env() -> Result<Stockfighter> {
+        StockfighterBuilder::from_env().and_then(|builder| build(builder, "environment", ENV_ALLOW_PLAINTEXT))
+    }
+
+    /// Client configured from the `[default]` profile of the TOML file at `path`
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stockfighter::{Stockfighter, default_config_path};
+    ///
+    /// let sf = Stockfighter::from_config(default_config_path().unwrap()).unwrap();
+    /// ```
+    pub fn from_config<P>(path: P) -> Result<Stockfighter> where P: AsRef<Path> {
+        Stockfighter::from_config_profile(path, DEFAULT_PROFILE)
+    }
+
+    /// Client configured from `profile` in the TOML file at `path`
+    pub fn from_config_profile<P>(path: P, profile: &str) -> Result<Stockfighter> where P: AsRef<Path> {
+        let source = path.as_ref().display().to_string();
+        StockfighterBuilder::from_config(path, profile).and_then(|builder| build(builder, &source, "allow_plaintext"))
+    }
+}
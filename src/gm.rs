@@ -0,0 +1,307 @@
+//! The gamemaster API, which starts and stops the levels that the order book API trades in
+//!
+//! The gamemaster lives under its own base URL (see `StockfighterBuilder::gm_url`) but takes the
+//! same API key as the order book, so a `Gamemaster` is made from a `Stockfighter` and shares its
+//! transport, retry policy and deadline. Only status checks are retried, as starting, stopping or
+//! resuming a level twice is not harmless, and no gamemaster call counts against the order book's
+//! rate limits.
+//!
+//! An instance can also be watched: a thread polls its status and turns the changes into
+//! `InstanceEvent`s on a `Subscription`, like the ticker tape's quotes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::result;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{IgnoredAny, DeserializeOwned};
+
+use stream::{self, StreamEvent, Subscription, Stats};
+use transport::Method;
+use {Result, Stockfighter, IntoChecked, Level, InstanceId, Call};
+
+/// A running level: where to trade and what to do
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LevelInstance {
+    pub ok: bool,
+    pub instance_id: usize,
+    pub account: String,
+    pub venues: Vec<String>,
+    pub tickers: Vec<String>,
+    pub seconds_per_trading_day: Option<u64>,
+    /// Starting balances by currency or symbol, in cents for currencies
+    #[serde(default)]
+    pub balances: BTreeMap<String, i64>,
+    /// The level's briefing by section, e.g. "Instructions" and "Order Types"
+    #[serde(default)]
+    pub instructions: BTreeMap<String, String>,
+}
+
+// Instance states the SDK does not know about are kept in `Other` instead of failing to decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstanceState {
+    Open,
+    Won,
+    Lost,
+    Other(String),
+}
+
+impl InstanceState {
+    /// The name the API uses for this state, e.g. `won`
+    pub fn as_str(&self) -> &str {
+        match *self {
+            InstanceState::Open => "open",
+            InstanceState::Won => "won",
+            InstanceState::Lost => "lost",
+            InstanceState::Other(ref other) => &other[..],
+        }
+    }
+}
+
+impl<'a> From<&'a str> for InstanceState {
+    fn from(s: &'a str) -> InstanceState {
+        match s {
+            "open" => InstanceState::Open,
+            "won" => InstanceState::Won,
+            "lost" => InstanceState::Lost,
+            other => InstanceState::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for InstanceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for InstanceState {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for InstanceState {
+    fn deserialize<D>(deserializer: D) -> result::Result<InstanceState, D::Error> where D: Deserializer<'de> {
+        let s = try!(String::deserialize(deserializer));
+        Ok(InstanceState::from(&s[..]))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceDetails {
+    pub trading_day: u32,
+    /// The trading day on which the level ends
+    pub end_of_the_world_day: u32,
+}
+
+/// Where a level instance is at
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstanceStatus {
+    pub ok: bool,
+    pub id: usize,
+    pub done: bool,
+    pub state: InstanceState,
+    pub details: Option<InstanceDetails>,
+    /// Messages for the player by kind, e.g. "info", "success", "warning" or "danger". They tell
+    /// how the level is won or lost.
+    #[serde(default)]
+    pub flash: BTreeMap<String, String>,
+}
+
+/// A change in a watched instance, see `Gamemaster::watch_instance`
+#[derive(Debug)]
+pub enum InstanceEvent {
+    /// Trading day `day` has begun
+    TradingDay { day: u32, end_of_the_world_day: u32 },
+    /// The gamemaster has a new message
+    Flash { kind: String, message: String },
+    /// The level was won. This is the last event.
+    Won(InstanceStatus),
+    /// The level was lost. This is the last event.
+    Lost(InstanceStatus),
+    /// The instance ended some other way, e.g. it was stopped. This is the last event.
+    Closed(InstanceStatus),
+}
+
+/// Client for the gamemaster API
+///
+/// # Example
+///
+/// ```no_run
+/// use stockfighter::{Stockfighter, Gamemaster};
+///
+/// let sf = Stockfighter::new("fake api key");
+/// let gm = Gamemaster::new(&sf);
+///
+/// let level = gm.start_level("first_steps").unwrap();
+/// println!("Trade {:?} on {:?} as {}", level.tickers, level.venues, level.account);
+///
+/// // ... later
+/// gm.stop(level.instance_id).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Gamemaster {
+    sf: Stockfighter,
+}
+
+impl Gamemaster {
+
+    /// Gamemaster client with the API key and settings of `sf`
+    pub fn new(sf: &Stockfighter) -> Gamemaster {
+        Gamemaster { sf: sf.clone() }
+    }
+
+    /// Start a new instance of `level`, e.g. `first_steps`. Starting a level that is already
+    /// running returns the running instance.
+    pub fn start_level<L>(&self, level: L) -> Result<LevelInstance> where L: IntoChecked<Level> {
+        let level = try!(level.into_checked());
+        self.request(Call::GamemasterAction, Method::Post, &format!("/levels/{}", level))
+    }
+
+    /// Start an instance over, with a new market
+    pub fn restart<I>(&self, instance: I) -> Result<LevelInstance> where I: IntoChecked<InstanceId> {
+        let instance = try!(instance.into_checked());
+        self.request(Call::GamemasterAction, Method::Post, &format!("/instances/{}/restart", instance))
+    }
+
+    /// Stop an instance for good
+    pub fn stop<I>(&self, instance: I) -> Result<()> where I: IntoChecked<InstanceId> {
+        let instance = try!(instance.into_checked());
+        let endpoint = format!("/instances/{}/stop", instance);
+        self.request::<IgnoredAny>(Call::GamemasterAction, Method::Post, &endpoint).map(|_| ())
+    }
+
+    /// Pick up an instance that is still running, e.g. after the bot restarted
+    pub fn resume<I>(&self, instance: I) -> Result<LevelInstance> where I: IntoChecked<InstanceId> {
+        let instance = try!(instance.into_checked());
+        self.request(Call::GamemasterAction, Method::Post, &format!("/instances/{}/resume", instance))
+    }
+
+    /// Get the status of an instance
+    pub fn instance_status<I>(&self, instance: I) -> Result<InstanceStatus> where I: IntoChecked<InstanceId> {
+        let instance = try!(instance.into_checked());
+        self.request(Call::GamemasterRead, Method::Get, &format!("/instances/{}", instance))
+    }
+
+    /// Poll the status of an instance every `interval` on a thread of its own, and send what
+    /// changes as `InstanceEvent`s
+    ///
+    /// The first poll is made before returning, so a bad instance id or key is reported to the
+    /// caller; the flash messages it finds are the first events. A poll that fails is sent as
+    /// `StreamEvent::Error` and polling goes on. The subscription ends after `Won`, `Lost` or
+    /// `Closed`, and `last_message_at` tells when the status was last polled.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use stockfighter::{Stockfighter, Gamemaster, InstanceEvent, StreamEvent};
+    ///
+    /// let sf = Stockfighter::new("fake api key");
+    /// let gm = Gamemaster::new(&sf);
+    /// let level = gm.start_level("first_steps").unwrap();
+    ///
+    /// for event in gm.watch_instance(level.instance_id, Duration::from_secs(1)).unwrap() {
+    ///     match event {
+    ///         StreamEvent::Message(InstanceEvent::TradingDay { day, .. }) => println!("Day {}", day),
+    ///         StreamEvent::Message(InstanceEvent::Flash { message, .. }) => println!("{}", message),
+    ///         StreamEvent::Message(InstanceEvent::Won(_)) => println!("Won!"),
+    ///         _ => (),
+    ///     }
+    /// }
+    /// ```
+    pub fn watch_instance<I>(&self, instance: I, interval: Duration) -> Result<Subscription<InstanceEvent>>
+        where I: IntoChecked<InstanceId> {
+
+        let instance = try!(instance.into_checked());
+        let first = try!(self.instance_status(instance));
+
+        let gm = self.clone();
+        let stats = Arc::new(Mutex::new(Stats { last_message: Some(Instant::now()), ping_rtt: None }));
+        let polled = stats.clone();
+        let (tx, rx) = mpsc::channel();
+
+        // The thread owns `tx` and drops it on the way out, which ends the subscription. Handing it
+        // back as the thread's state would keep it alive in the handle.
+        let handle = stream::spawn_thread(stats, move |stop| {
+            trace!("Spawned thread watching instance {}", instance);
+            let mut previous: Option<InstanceStatus> = None;
+            let mut next = Ok(first);
+
+            loop {
+                match next {
+                    Ok(status) => {
+                        polled.lock().unwrap().last_message = Some(Instant::now());
+                        let (events, ended) = changes(previous.as_ref(), &status);
+                        for event in events {
+                            // The subscriber may have gone away, which is fine
+                            let _ = tx.send(StreamEvent::Message(event));
+                        }
+                        if ended {
+                            return;
+                        }
+                        previous = Some(status);
+                    }
+                    Err(err) => {
+                        debug!("Polling instance {} failed: {}", instance, err);
+                        let _ = tx.send(StreamEvent::Error(err));
+                    }
+                }
+
+                if !stream::sleep_unless_stopped(interval, stop) {
+                    return;
+                }
+                next = gm.instance_status(instance);
+            }
+        });
+
+        Ok(stream::thread_subscription(rx, handle))
+    }
+
+    // No gamemaster call takes a body
+    fn request<T>(&self, call: Call, method: Method, endpoint: &str) -> Result<T> where T: DeserializeOwned {
+        self.sf.request_to(&self.sf.inner.gm_url, call, method, endpoint, None)
+    }
+}
+
+/// The events between two polls of an instance, and whether it has ended
+fn changes(previous: Option<&InstanceStatus>, current: &InstanceStatus) -> (Vec<InstanceEvent>, bool) {
+    let mut events = Vec::new();
+
+    let day = |status: &InstanceStatus| status.details.as_ref().map(|details| details.trading_day);
+    if let (Some(before), Some(details)) = (previous.and_then(|status| day(status)), current.details.as_ref()) {
+        if details.trading_day > before {
+            events.push(InstanceEvent::TradingDay {
+                day: details.trading_day,
+                end_of_the_world_day: details.end_of_the_world_day,
+            });
+        }
+    }
+
+    for (kind, message) in &current.flash {
+        if previous.and_then(|status| status.flash.get(kind)) != Some(message) {
+            events.push(InstanceEvent::Flash { kind: kind.clone(), message: message.clone() });
+        }
+    }
+
+    let ended = match current.state {
+        InstanceState::Won => Some(InstanceEvent::Won(current.clone())),
+        InstanceState::Lost => Some(InstanceEvent::Lost(current.clone())),
+        _ if current.done => Some(InstanceEvent::Closed(current.clone())),
+        _ => None,
+    };
+
+    match ended {
+        Some(event) => {
+            events.push(event);
+            (events, true)
+        }
+        None => (events, false),
+    }
+}
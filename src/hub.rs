@@ -0,0 +1,599 @@
+//! Many feed subscriptions served by one thread
+//!
+//! `MarketDataHub` keeps one socket per feed URL, however many subscribers it has, and reads all
+//! of them on a single thread. The sockets are non-blocking and every message waiting in one is
+//! handed on in each pass, so a quiet feed never holds up a busy one. Reconnecting happens on a
+//! second thread, which hands new connections back to the hub.
+//!
+//! Each socket is pinged and watched for silence like a feed of its own, and its subscribers share
+//! its last message time and ping round trip.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender as EventSender, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use websocket::{Message, Sender};
+use websocket::client;
+use websocket::message::Type;
+use websocket::stream::WebSocketStream;
+
+use stream::{self, StreamEvent, Subscription, FeedConfig, Keepalive, Stats};
+use ws::{self, Connection, NonBlockingReceiver};
+use {Result, Stockfighter, IntoChecked, Account, Venue, Symbol};
+use {TickerTapeQuote, ExecutionOrdersStatuses};
+
+/// How long, in milliseconds, the event loop waits for commands after a pass that read nothing
+const PASS_WAIT: u64 = 2;
+
+/// How long, in milliseconds, the event loop waits for commands while no feed is open
+const IDLE_WAIT: u64 = 100;
+
+/// Serves any number of ticker tape and executions subscriptions from a single thread
+///
+/// Subscribers to the same feed share one WebSocket. Subscriptions can be added from any thread
+/// while the hub runs, and are removed by dropping or stopping them; a socket is closed once its
+/// last subscriber is gone. A dropped socket is reconnected according to the client's reconnect
+/// policy, and every subscriber is told with `StreamEvent::Disconnected` and
+/// `StreamEvent::Reconnected`, and a socket that goes quiet with `StreamEvent::Stale`. Messages
+/// that cannot be decoded arrive as `StreamEvent::Error`; connection errors are only logged.
+///
+/// # Example
+///
+/// ```no_run
+/// use stockfighter::{Stockfighter, MarketDataHub, StreamEvent};
+///
+/// let sf = Stockfighter::new("fake api key");
+/// let hub = MarketDataHub::new(&sf);
+///
+/// let foobar = hub.ticker_tape_venue_stock("EXB123456", "TESTEX", "FOOBAR").unwrap();
+/// let bazqux = hub.ticker_tape_venue_stock("EXB123456", "TESTEX", "BAZQUX").unwrap();
+/// let fills = hub.executions_venue("EXB123456", "TESTEX").unwrap();
+///
+/// for event in fills {
+///     if let StreamEvent::Message(execution) = event {
+///         println!("{:?}", execution);
+///     }
+/// }
+/// ```
+pub struct MarketDataHub {
+    sf: Stockfighter,
+    commands: Mutex<mpsc::Sender<Command>>,
+    // The open feeds by URL, with what their subscribers are shown of them
+    open: Arc<Mutex<HashMap<String, Arc<Mutex<Stats>>>>>,
+    next_id: AtomicUsize,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+    connector: Option<thread::JoinHandle<()>>,
+}
+
+impl MarketDataHub {
+
+    /// Start a hub that connects with the settings of `sf`
+    pub fn new(sf: &Stockfighter) -> MarketDataHub {
+        let (tx, rx) = mpsc::channel();
+        let (attempts_tx, attempts_rx) = mpsc::channel();
+        let open = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = sf.feed_config();
+
+        let connect_timeout = config.connect_timeout;
+        let connected = tx.clone();
+        let connector = thread::spawn(move || connect_all(attempts_rx, connected, connect_timeout));
+
+        let mut event_loop = EventLoop {
+            feeds: Vec::new(),
+            commands: rx,
+            connector: attempts_tx,
+            open: open.clone(),
+            stop: stop.clone(),
+            config: config,
+            next_generation: 0,
+        };
+        let thread = thread::spawn(move || event_loop.run());
+
+        MarketDataHub {
+            sf: sf.clone(),
+            commands: Mutex::new(tx),
+            open: open,
+            next_id: AtomicUsize::new(0),
+            stop: stop,
+            thread: Some(thread),
+            connector: Some(connector),
+        }
+    }
+
+    pub fn ticker_tape_venue<A, V>(&self, account: A, venue: V) -> Result<Subscription<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
+
+        let url = try!(self.sf.feed_url("tickertape", account, venue, None::<Symbol>));
+        self.subscribe(url, Consumers::Quotes)
+    }
+
+    pub fn ticker_tape_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S) -> Result<Subscription<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let url = try!(self.sf.feed_url("tickertape", account, venue, Some(stock)));
+        self.subscribe(url, Consumers::Quotes)
+    }
+
+    pub fn executions_venue<A, V>(&self, account: A, venue: V) -> Result<Subscription<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
+
+        let url = try!(self.sf.feed_url("executions", account, venue, None::<Symbol>));
+        self.subscribe(url, Consumers::Executions)
+    }
+
+    pub fn executions_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S)
+                                           -> Result<Subscription<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
+
+        let url = try!(self.sf.feed_url("executions", account, venue, Some(stock)));
+        self.subscribe(url, Consumers::Executions)
+    }
+
+    /// How many sockets the hub has open or is reconnecting
+    pub fn feed_count(&self) -> usize {
+        self.open.lock().unwrap().len()
+    }
+
+    /// Register a consumer of `url`. The first subscriber to a feed connects it on the calling
+    /// thread, so a bad URL or key is reported here rather than by the hub.
+    fn subscribe<T>(&self, url: String, consumers: fn(Vec<(usize, EventSender<StreamEvent<T>>)>) -> Consumers)
+                    -> Result<Subscription<T>> {
+
+        let open = self.open.lock().unwrap().get(&url).cloned();
+        let (connection, stats) = match open {
+            Some(stats) => (None, stats),
+            None => {
+                let connection = try!(ws::connect(&url, self.sf.inner.connect_timeout));
+                let stats = self.open.lock().unwrap().entry(url.clone())
+                    .or_insert_with(|| Arc::new(Mutex::new(Stats::default())))
+                    .clone();
+                (Some(connection), stats)
+            }
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = mpsc::channel();
+        let commands = self.commands.lock().unwrap().clone();
+
+        let _ = commands.send(Command::Add {
+            url: url,
+            consumers: consumers(vec![(id, tx)]),
+            connection: connection,
+            stats: stats.clone(),
+        });
+        Ok(stream::hub_subscription(rx, Registration { id: id, commands: commands }, stats))
+    }
+}
+
+impl Drop for MarketDataHub {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // The event loop has dropped its end of the connector's queue, so it ends after the
+        // attempt it may be making
+        if let Some(connector) = self.connector.take() {
+            let _ = connector.join();
+        }
+    }
+}
+
+/// Removes its subscriber from the hub when dropped
+pub struct Registration {
+    id: usize,
+    commands: mpsc::Sender<Command>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Remove(self.id));
+    }
+}
+
+enum Command {
+    Add { url: String, consumers: Consumers, connection: Option<Connection>, stats: Arc<Mutex<Stats>> },
+    Remove(usize),
+    Reconnected { url: String, generation: usize, result: Result<Connection> },
+}
+
+/// A reconnect the connector thread is to make once `due`
+struct Attempt {
+    url: String,
+    generation: usize,
+    due: Instant,
+}
+
+// The subscribers of a feed, each with its id
+enum Consumers {
+    Quotes(Vec<(usize, EventSender<StreamEvent<TickerTapeQuote>>)>),
+    Executions(Vec<(usize, EventSender<StreamEvent<ExecutionOrdersStatuses>>)>),
+}
+
+impl Consumers {
+
+    /// Add the subscribers in `other`, which must be of the same kind
+    fn append(&mut self, other: Consumers) {
+        match (self, other) {
+            (&mut Consumers::Quotes(ref mut mine), Consumers::Quotes(theirs)) => mine.extend(theirs),
+            (&mut Consumers::Executions(ref mut mine), Consumers::Executions(theirs)) => mine.extend(theirs),
+            _ => warn!("Subscriber of the wrong kind for a feed"),
+        }
+    }
+
+    fn remove(&mut self, id: usize) -> bool {
+        match *self {
+            Consumers::Quotes(ref mut consumers) => remove(consumers, id),
+            Consumers::Executions(ref mut consumers) => remove(consumers, id),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match *self {
+            Consumers::Quotes(ref consumers) => consumers.is_empty(),
+            Consumers::Executions(ref consumers) => consumers.is_empty(),
+        }
+    }
+
+    /// Hand a text frame to every subscriber. It is decoded for each of them, as the decoded
+    /// message and any decoding error are not `Clone`.
+    fn text(&mut self, payload: &[u8]) {
+        match *self {
+            Consumers::Quotes(ref mut consumers) =>
+                send_all(consumers, || decoded(stream::decode_text(payload, stream::decode_quote))),
+            Consumers::Executions(ref mut consumers) =>
+                send_all(consumers, || decoded(stream::decode_text(payload, stream::decode_execution))),
+        }
+    }
+
+    fn notify(&mut self, notice: Notice) {
+        match *self {
+            Consumers::Quotes(ref mut consumers) => send_all(consumers, || notice.event()),
+            Consumers::Executions(ref mut consumers) => send_all(consumers, || notice.event()),
+        }
+    }
+}
+
+// The events about a socket, which go to every subscriber whatever it decodes messages into
+#[derive(Clone, Copy)]
+enum Notice {
+    Disconnected,
+    Reconnected(Duration),
+    Stale(Instant),
+}
+
+impl Notice {
+    fn event<T>(self) -> StreamEvent<T> {
+        match self {
+            Notice::Disconnected => StreamEvent::Disconnected,
+            Notice::Reconnected(downtime) => StreamEvent::Reconnected { downtime: downtime },
+            Notice::Stale(since) => StreamEvent::Stale { since: since },
+        }
+    }
+}
+
+fn remove<T>(consumers: &mut Vec<(usize, EventSender<StreamEvent<T>>)>, id: usize) -> bool {
+    let before = consumers.len();
+    consumers.retain(|&(consumer, _)| consumer != id);
+    consumers.len() != before
+}
+
+/// Send an event made by `event` to every subscriber, forgetting those that have gone away
+fn send_all<T, F>(consumers: &mut Vec<(usize, EventSender<StreamEvent<T>>)>, mut event: F)
+    where F: FnMut() -> StreamEvent<T> {
+
+    consumers.retain(|&(_, ref tx)| tx.send(event()).is_ok());
+}
+
+fn decoded<T>(result: Result<T>) -> StreamEvent<T> {
+    match result {
+        Ok(message) => StreamEvent::Message(message),
+        Err(err) => StreamEvent::Error(err),
+    }
+}
+
+struct Socket {
+    sender: client::Sender<WebSocketStream>,
+    receiver: NonBlockingReceiver,
+    keepalive: Keepalive,
+}
+
+struct Feed {
+    url: String,
+    consumers: Consumers,
+    // `None` while reconnecting
+    socket: Option<Socket>,
+    stats: Arc<Mutex<Stats>>,
+    down_since: Option<Instant>,
+    attempt: u32,
+    // Changed whenever a reconnect is scheduled, so that the results of earlier ones are ignored
+    generation: usize,
+}
+
+struct EventLoop {
+    feeds: Vec<Feed>,
+    commands: mpsc::Receiver<Command>,
+    connector: mpsc::Sender<Attempt>,
+    open: Arc<Mutex<HashMap<String, Arc<Mutex<Stats>>>>>,
+    stop: Arc<AtomicBool>,
+    config: FeedConfig,
+    next_generation: usize,
+}
+
+impl EventLoop {
+
+    fn run(&mut self) {
+        trace!("Spawned thread for market data hub");
+        while !self.stop.load(Ordering::SeqCst) {
+            if self.feeds.is_empty() {
+                match self.commands.recv_timeout(Duration::from_millis(IDLE_WAIT)) {
+                    Ok(command) => self.handle(command),
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            while let Ok(command) = self.commands.try_recv() {
+                self.handle(command);
+            }
+
+            let mut read = 0;
+            for index in 0..self.feeds.len() {
+                read += self.read(index);
+            }
+
+            // Nothing was waiting, so give the sockets a moment rather than spinning
+            if read == 0 {
+                match self.commands.recv_timeout(Duration::from_millis(PASS_WAIT)) {
+                    Ok(command) => self.handle(command),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        }
+
+        for feed in &mut self.feeds {
+            if let Some(ref mut socket) = feed.socket {
+                let _ = socket.sender.send_message(&Message::close());
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Add { url, consumers, connection, stats } => {
+                if let Some(feed) = self.feeds.iter_mut().find(|feed| feed.url == url) {
+                    // Raced with another first subscriber; the extra connection is not needed
+                    feed.consumers.append(consumers);
+                    return;
+                }
+
+                self.open.lock().unwrap().insert(url.clone(), stats.clone());
+                let socket = match connection.map(|connection| socket(connection, &self.config)) {
+                    Some(Ok(socket)) => Some(socket),
+                    Some(Err(err)) => {
+                        debug!("Could not set up websocket {}: {}", url, err);
+                        None
+                    }
+                    None => None,
+                };
+                let reconnecting = socket.is_none();
+                let generation = self.next_generation();
+                self.feeds.push(Feed {
+                    url: url,
+                    consumers: consumers,
+                    socket: socket,
+                    stats: stats,
+                    down_since: None,
+                    attempt: 0,
+                    generation: generation,
+                });
+
+                // The feed closed between the subscriber looking for it and adding itself, or its
+                // socket could not be made non-blocking
+                if reconnecting {
+                    let index = self.feeds.len() - 1;
+                    self.feeds[index].down_since = Some(Instant::now());
+                    self.reconnect_later(index);
+                }
+            }
+            Command::Remove(id) => {
+                if let Some(index) = self.feeds.iter_mut().position(|feed| feed.consumers.remove(id)) {
+                    if self.feeds[index].consumers.is_empty() {
+                        self.close(index);
+                    }
+                }
+            }
+            Command::Reconnected { url, generation, result } => {
+                let current = self.feeds.iter().position(|feed| {
+                    feed.url == url && feed.generation == generation && feed.socket.is_none()
+                });
+                let index = match current {
+                    Some(index) => index,
+                    None => {
+                        trace!("Dropping the result of an outdated reconnect to websocket {}", url);
+                        return;
+                    }
+                };
+
+                match result.and_then(|connection| socket(connection, &self.config)) {
+                    Ok(socket) => {
+                        info!("Reconnected websocket {} after {} attempts", url, self.feeds[index].attempt);
+                        let feed = &mut self.feeds[index];
+                        let downtime = feed.down_since.take().map_or(Duration::from_secs(0), |since| since.elapsed());
+                        feed.socket = Some(socket);
+                        feed.attempt = 0;
+                        feed.consumers.notify(Notice::Reconnected(downtime));
+                    }
+                    Err(ref err) if self.feeds[index].attempt < self.config.reconnect.attempts() => {
+                        debug!("Reconnecting websocket {} failed ({}), attempt {}", url, err, self.feeds[index].attempt);
+                        self.reconnect_later(index);
+                    }
+                    Err(_) => {
+                        warn!("Giving up on websocket {} after {} attempts", url, self.feeds[index].attempt);
+                        // Dropping the subscribers' senders ends their subscriptions
+                        self.close(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hand on every message waiting in a feed's socket, returning how many there were
+    fn read(&mut self, index: usize) -> usize {
+        let read = {
+            let feed = &mut self.feeds[index];
+            match feed.socket {
+                Some(ref mut socket) => read_socket(&feed.url, socket, &mut feed.consumers, &feed.stats),
+                None => return 0,
+            }
+        };
+
+        match read {
+            Some(read) => read,
+            None => {
+                self.lost(index);
+                0
+            }
+        }
+    }
+
+    fn lost(&mut self, index: usize) {
+        {
+            let feed = &mut self.feeds[index];
+            feed.socket = None;
+            feed.down_since = Some(Instant::now());
+            feed.consumers.notify(Notice::Disconnected);
+        }
+        self.reconnect_later(index);
+    }
+
+    /// Have the connector thread connect the feed again after the reconnect policy's delay
+    fn reconnect_later(&mut self, index: usize) {
+        let generation = self.next_generation();
+        let feed = &mut self.feeds[index];
+        feed.attempt += 1;
+        feed.generation = generation;
+
+        let _ = self.connector.send(Attempt {
+            url: feed.url.clone(),
+            generation: generation,
+            due: Instant::now() + self.config.reconnect.delay(feed.attempt),
+        });
+    }
+
+    fn next_generation(&mut self) -> usize {
+        self.next_generation += 1;
+        self.next_generation
+    }
+
+    fn close(&mut self, index: usize) {
+        let mut feed = self.feeds.remove(index);
+        debug!("Closing websocket {}", feed.url);
+        if let Some(ref mut socket) = feed.socket {
+            let _ = socket.sender.send_message(&Message::close());
+        }
+        self.open.lock().unwrap().remove(&feed.url);
+    }
+}
+
+/// Make the connections the event loop asks for, each once it is due, and hand them back to it.
+/// Runs until the event loop drops its end of `attempts`.
+fn connect_all(attempts: mpsc::Receiver<Attempt>, connected: mpsc::Sender<Command>, connect_timeout: Option<Duration>) {
+    trace!("Spawned thread for market data hub reconnects");
+    let mut pending: Vec<Attempt> = Vec::new();
+
+    loop {
+        let now = Instant::now();
+        let next = pending.iter().map(|attempt| attempt.due).min();
+        let received = match next {
+            Some(due) if due <= now => None,
+            Some(due) => match attempts.recv_timeout(due - now) {
+                Ok(attempt) => Some(attempt),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return,
+            },
+            None => match attempts.recv() {
+                Ok(attempt) => Some(attempt),
+                Err(_) => return,
+            },
+        };
+        if let Some(attempt) = received {
+            pending.push(attempt);
+            continue;
+        }
+
+        let due = match pending.iter().enumerate().min_by_key(|&(_, attempt)| attempt.due) {
+            Some((index, _)) => pending.swap_remove(index),
+            None => continue,
+        };
+        let result = ws::connect(&due.url, connect_timeout);
+        let reconnected = Command::Reconnected { url: due.url, generation: due.generation, result: result };
+        if connected.send(reconnected).is_err() {
+            return;
+        }
+    }
+}
+
+fn socket(connection: Connection, config: &FeedConfig) -> Result<Socket> {
+    let (sender, receiver) = connection.split();
+    Ok(Socket { sender: sender, receiver: try!(NonBlockingReceiver::new(receiver)), keepalive: Keepalive::new(config) })
+}
+
+/// Ping `socket` if it is time, and hand on every message waiting in it. Returns how many
+/// messages there were, or `None` once the socket is no longer usable.
+fn read_socket(url: &str, socket: &mut Socket, consumers: &mut Consumers, stats: &Mutex<Stats>) -> Option<usize> {
+    let now = Instant::now();
+    if let Some(ping) = socket.keepalive.ping(now) {
+        if let Err(err) = socket.sender.send_message(&ping) {
+            debug!("Lost websocket {}: {}", url, err);
+            return None;
+        }
+    }
+    if let Some(since) = socket.keepalive.stale(now) {
+        warn!("Websocket {} has been silent for {:?}", url, now - since);
+        consumers.notify(Notice::Stale(since));
+    }
+
+    let messages = match socket.receiver.recv_available() {
+        Ok(messages) => messages,
+        Err(err) => {
+            debug!("Lost websocket {}: {}", url, err);
+            return None;
+        }
+    };
+    let read = messages.len();
+
+    for message in messages {
+        trace!("Received message {:?} from websocket {}", message, url);
+
+        if let Some(rtt) = socket.keepalive.received(&message) {
+            stats.lock().unwrap().ping_rtt = Some(rtt);
+        }
+
+        match message.opcode {
+            Type::Text => {
+                stats.lock().unwrap().last_message = Some(Instant::now());
+                consumers.text(&message.payload);
+            }
+            Type::Close => {
+                debug!("Websocket {} closed by the server", url);
+                let _ = socket.sender.send_message(&Message::close());
+                return None;
+            }
+            Type::Ping => {
+                if let Err(err) = socket.sender.send_message(&Message::pong(message.payload)) {
+                    debug!("Lost websocket {}: {}", url, err);
+                    return None;
+                }
+            }
+            _ => (),
+        }
+    }
+    Some(read)
+}
@@ -1,204 +1,302 @@
 #[macro_use]
 extern crate log;
-#[macro_use]
 extern crate hyper;
-extern crate rustc_serialize;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate websocket;
+extern crate openssl;
+extern crate rand;
+extern crate chrono;
+extern crate toml;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate futures_cpupool;
 
 use std::fmt;
-use std::io::{self, Read};
+use std::io;
 use std::error::Error;
 use std::result;
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
-use hyper::Client;
-use hyper::status::StatusCode;
-
-use rustc_serialize::json;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
 
-use websocket::{Message, Sender, Receiver};
-use websocket::message::Type;
 use websocket::client::request::Url;
-use websocket::Client as WSClient;
 use websocket::result::WebSocketError;
 
-header! { (XStarfighterAuthorization, "X-Starfighter-Authorization") => [String] }
+pub mod transport;
+mod retry;
+mod ratelimit;
+mod ws;
+mod stream;
+mod hub;
+mod timestamp;
+mod types;
+mod price;
+mod config;
+mod gm;
+mod session;
+#[cfg(feature = "async")]
+mod async_client;
+
+pub use transport::{Transport, HyperTransport, ScriptedTransport};
+pub use retry::{RetryPolicy, is_transient};
+pub use ratelimit::{RateLimiter, RateLimitMode, RequestKind};
+pub use timestamp::Timestamp;
+pub use stream::{StreamEvent, Subscription, SubscriptionHandle};
+pub use hub::MarketDataHub;
+pub use types::{IntoChecked, Venue, Symbol, Account, OrderId, Qty, Level, InstanceId};
+pub use gm::{Gamemaster, LevelInstance, InstanceStatus, InstanceState, InstanceDetails, InstanceEvent};
+pub use session::LevelSession;
+pub use price::Price;
+pub use config::{default_config_path, DEFAULT_PROFILE, ENV_API_KEY, ENV_ACCOUNT, ENV_VENUE};
+pub use config::{ENV_BASE_URL, ENV_WS_URL, ENV_GM_URL, ENV_ALLOW_PLAINTEXT};
+#[cfg(feature = "async")]
+pub use async_client::{AsyncStockfighter, StockfighterFuture, FeedStream};
+
+use transport::{Method, Request, AUTH_HEADER};
+use transport::{DEFAULT_CONNECT_TIMEOUT, DEFAULT_READ_TIMEOUT, DEFAULT_MAX_IDLE_CONNECTIONS};
+use stream::FeedConfig;
+
+// Every API response carries `ok`, and an `error` message when `ok` is false
+#[derive(Deserialize)]
+struct ApiStatus {
+    ok: Option<bool>,
+    error: Option<String>,
+}
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Serialize, Deserialize)]
 struct Heartbeat {
     ok: bool,
     error: String,
 }
 
-#[derive(RustcDecodable, RustcEncodable)]
+#[derive(Serialize, Deserialize)]
 struct VenueHeartbeat {
     ok: bool,
     // venue is not present on error
     venue: Option<String>,
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Quote {
     pub ok: bool,
     pub symbol: String,
     pub venue: String,
-    pub bid: Option<usize>,
-    pub ask: Option<usize>,
-    pub bidSize: Option<usize>,
-    pub askSize: Option<usize>,
-    pub bidDepth: Option<usize>,
-    pub askDepth: Option<usize>,
-    pub last: usize,
-    pub lastSize: Option<usize>,
-    pub lastTrade: Option<String>,
-    pub quoteTime: Option<String>,
-}
-
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)]
+    pub bid: Option<Price>,
+    pub ask: Option<Price>,
+    pub bid_size: Option<usize>,
+    pub ask_size: Option<usize>,
+    pub bid_depth: Option<usize>,
+    pub ask_depth: Option<usize>,
+    pub last: Price,
+    pub last_size: Option<usize>,
+    pub last_trade: Option<Timestamp>,
+    pub quote_time: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct TickerTapeQuote {
     pub symbol: String,
     pub venue: String,
-    pub bid: Option<usize>,
-    pub ask: Option<usize>,
-    pub bidSize: Option<usize>,
-    pub askSize: Option<usize>,
-    pub bidDepth: Option<usize>,
-    pub askDepth: Option<usize>,
-    pub last: usize,
-    pub lastSize: Option<usize>,
-    pub lastTrade: Option<String>,
-    pub quoteTime: Option<String>,
-}
-
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+    pub bid: Option<Price>,
+    pub ask: Option<Price>,
+    pub bid_size: Option<usize>,
+    pub ask_size: Option<usize>,
+    pub bid_depth: Option<usize>,
+    pub ask_depth: Option<usize>,
+    pub last: Price,
+    pub last_size: Option<usize>,
+    pub last_trade: Option<Timestamp>,
+    pub quote_time: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TickerTape {
     pub ok: bool,
     pub quote: TickerTapeQuote,
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)] //when I make it order_type it doesn't work
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Order {
     pub account: String,
     pub venue: String,
     pub stock: String,
-    pub price: usize,
+    pub price: Price,
     pub qty: usize,
     pub direction: OrderDirection,
-    pub orderType: String
+    pub order_type: OrderType,
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Fill {
-    pub price: Option<usize>,
+    pub price: Option<Price>,
     pub qty: Option<usize>,
-    pub ts: Option<String>
+    pub ts: Option<Timestamp>
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
 pub enum OrderDirection {
-    buy,
-    sell,
+    Buy,
+    Sell,
 }
 
 // https://starfighter.readme.io/docs/place-new-order#order-types
-// Note that in the Order and OrderStatus structs the orderType is
-// represented as a string. This is because some of the valid API
-// values are invalid symbols in rust (e.g. "fill-or-kill") and
-// rustc_serialize autoserialization doesn't support field renaming.
+// Order types the SDK does not know about are kept in `Other` instead of failing to decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OrderType {
     Limit,
     Market,
     FillOrKill,
     ImmediateOrCancel,
+    Other(String),
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)]
+impl OrderType {
+    /// The name the API uses for this order type, e.g. `fill-or-kill`
+    pub fn as_str(&self) -> &str {
+        match *self {
+            OrderType::Limit => "limit",
+            OrderType::Market => "market",
+            OrderType::FillOrKill => "fill-or-kill",
+            OrderType::ImmediateOrCancel => "immediate-or-cancel",
+            OrderType::Other(ref other) => &other[..],
+        }
+    }
+}
+
+impl<'a> From<&'a str> for OrderType {
+    fn from(s: &'a str) -> OrderType {
+        match s {
+            "limit" => OrderType::Limit,
+            "market" => OrderType::Market,
+            "fill-or-kill" => OrderType::FillOrKill,
+            "immediate-or-cancel" => OrderType::ImmediateOrCancel,
+            other => OrderType::Other(other.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for OrderType {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for OrderType {
+    fn deserialize<D>(deserializer: D) -> result::Result<OrderType, D::Error> where D: Deserializer<'de> {
+        let s = try!(String::deserialize(deserializer));
+        Ok(OrderType::from(&s[..]))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct OrderStatus {
     pub ok: bool,
     pub symbol: Option<String>,
     pub venue: Option<String>,
     pub direction: Option<OrderDirection>,
-    pub originalQty: Option<usize>,
+    pub original_qty: Option<usize>,
     pub qty: Option<usize>,
-    pub price: Option<usize>,
-    pub orderType: Option<String>,
+    pub price: Option<Price>,
+    pub order_type: Option<OrderType>,
     pub id: Option<usize>,
     pub account: Option<String>,
-    pub ts: Option<String>,
+    pub ts: Option<Timestamp>,
     pub fills: Option<Vec<Fill>>,
-    pub totalFilled: Option<usize>,
+    pub total_filled: Option<usize>,
     pub open: Option<bool>
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct StockTicker {
     pub name: String,
     pub symbol: String,
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct StockList {
     pub ok: bool,
     pub symbols: Vec< StockTicker>,
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct BidAsk {
-    pub price: usize,
+    pub price: Price,
     pub qty: usize,
-    pub isBuy: bool
+    pub is_buy: bool
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct OrderbookList {
     pub ok: bool,
     pub venue: String,
     pub symbol: String,
     pub bids: Option<Vec< BidAsk >>,
     pub asks: Option<Vec< BidAsk >>,
-    pub ts: String
+    pub ts: Timestamp
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct StockOrdersStatuses {
     pub ok: bool,
     pub venue: String,
     pub orders: Vec< Order >
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug)]
-#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct ExecutionOrdersStatuses {
     pub ok: bool,
     pub account: String,
     pub venue: String,
     pub symbol: String,
     pub order: OrderStatus,
-    pub standingId: usize,
-    pub incomingId: usize,
-    pub price: usize,
+    pub standing_id: usize,
+    pub incoming_id: usize,
+    pub price: Price,
     pub filled: usize,
-    pub filledAt: String,
-    pub standingComplete: bool,
-    pub incomingComplete: bool,
+    pub filled_at: Timestamp,
+    pub standing_complete: bool,
+    pub incoming_complete: bool,
 }
 
 #[derive(Debug)]
 pub enum StockfighterError {
-    ApiDown,
+    ApiDown(String), // With the reason the API gave, if any
     VenueDown(String), // Also means unknown venue
-    ApiError,
+    Api { status: u16, endpoint: String, message: String },
+    Unauthorized(String),
+    NotFound(String), // Unknown venue, stock or order
+    RateLimited,
     Hyper(hyper::error::Error),
-    JsonDecoder(rustc_serialize::json::DecoderError),
-    JsonEncoder(rustc_serialize::json::EncoderError),
+    Json(serde_json::Error),
     Io(io::Error),
     WebSocket(WebSocketError),
+    InvalidUrl(String),
+    Timeout,
+    InvalidTimestamp(String),
+    InvalidArgument(String),
+    Config(String),
+    /// A feed message that could not be decoded, with the payload as received
+    Decode { payload: String, cause: Box<Error + Send + Sync> },
 }
 
 impl From<hyper::error::Error> for StockfighterError {
@@ -207,15 +305,9 @@ impl From<hyper::error::Error> for StockfighterError {
     }
 }
 
-impl From<rustc_serialize::json::DecoderError> for StockfighterError {
-    fn from(err: rustc_serialize::json::DecoderError) -> Self {
-        StockfighterError::JsonDecoder(err)
-    }
-}
-
-impl From<rustc_serialize::json::EncoderError> for StockfighterError {
-    fn from(err: rustc_serialize::json::EncoderError) -> Self {
-        StockfighterError::JsonEncoder(err)
+impl From<serde_json::Error> for StockfighterError {
+    fn from(err: serde_json::Error) -> Self {
+        StockfighterError::Json(err)
     }
 }
 
@@ -234,14 +326,24 @@ impl From<WebSocketError> for StockfighterError {
 impl fmt::Display for StockfighterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            StockfighterError::ApiDown => write!(f, "API down"),
+            StockfighterError::ApiDown(ref message) => write!(f, "API down: {}", message),
             StockfighterError::VenueDown(ref venue) => write!(f, "Venue down: {}", venue),
-            StockfighterError::ApiError => write!(f, "API error"),
+            StockfighterError::Api { status, ref endpoint, ref message } =>
+                write!(f, "API error {} from {}: {}", status, endpoint, message),
+            StockfighterError::Unauthorized(ref message) => write!(f, "Unauthorized: {}", message),
+            StockfighterError::NotFound(ref message) => write!(f, "Not found: {}", message),
+            StockfighterError::RateLimited => write!(f, "Rate limited"),
             StockfighterError::Hyper(ref err) => write!(f, "{}", err),
-            StockfighterError::JsonDecoder(ref err) => write!(f, "{}", err),
-            StockfighterError::JsonEncoder(ref err) => write!(f, "{}", err),
+            StockfighterError::Json(ref err) => write!(f, "{}", err),
             StockfighterError::Io(ref err) => write!(f, "{}", err),
             StockfighterError::WebSocket(ref err) => write!(f, "{}", err),
+            StockfighterError::InvalidUrl(ref url) => write!(f, "Invalid URL: {}", url),
+            StockfighterError::Timeout => write!(f, "Timed out"),
+            StockfighterError::InvalidTimestamp(ref ts) => write!(f, "Invalid timestamp: {}", ts),
+            StockfighterError::InvalidArgument(ref message) => write!(f, "Invalid argument: {}", message),
+            StockfighterError::Config(ref message) => write!(f, "Configuration error: {}", message),
+            StockfighterError::Decode { ref payload, ref cause } =>
+                write!(f, "Cannot decode message ({}): {}", cause, payload),
         }
     }
 }
@@ -249,24 +351,32 @@ impl fmt::Display for StockfighterError {
 impl Error for StockfighterError {
     fn description(&self) -> &str {
         match *self {
-            StockfighterError::ApiDown => "API down",
+            StockfighterError::ApiDown(_) => "API down",
             StockfighterError::VenueDown(_) => "Venue down",
-            StockfighterError::ApiError => "API error",
+            StockfighterError::Api { .. } => "API error",
+            StockfighterError::Unauthorized(_) => "Unauthorized",
+            StockfighterError::NotFound(_) => "Not found",
+            StockfighterError::RateLimited => "Rate limited",
             StockfighterError::Hyper(ref err) => err.description(),
-            StockfighterError::JsonDecoder(ref err) => err.description(),
-            StockfighterError::JsonEncoder(ref err) => err.description(),
+            StockfighterError::Json(ref err) => err.description(),
             StockfighterError::Io(ref err) => err.description(),
             StockfighterError::WebSocket(ref err) => err.description(),
+            StockfighterError::InvalidUrl(_) => "Invalid URL",
+            StockfighterError::Timeout => "Timed out",
+            StockfighterError::InvalidTimestamp(_) => "Invalid timestamp",
+            StockfighterError::InvalidArgument(_) => "Invalid argument",
+            StockfighterError::Config(_) => "Configuration error",
+            StockfighterError::Decode { .. } => "Cannot decode message",
         }
     }
 
     fn cause(&self) -> Option<&Error> {
         match *self {
             StockfighterError::Hyper(ref err) => Some(err as &Error),
-            StockfighterError::JsonDecoder(ref err) => Some(err as &Error),
-            StockfighterError::JsonEncoder(ref err) => Some(err as &Error),
+            StockfighterError::Json(ref err) => Some(err as &Error),
             StockfighterError::Io(ref err) => Some(err as &Error),
             StockfighterError::WebSocket(ref err) => Some(err as &Error),
+            StockfighterError::Decode { ref cause, .. } => Some(&**cause as &Error),
             _ => None
         }
     }
@@ -274,48 +384,582 @@ impl Error for StockfighterError {
 
 pub type Result<T> = result::Result<T, StockfighterError>;
 
-pub struct Stockfighter {
+/// Base URL of the production order book REST API
+pub const DEFAULT_BASE_URL: &'static str = "https://api.stockfighter.io/ob/api";
+
+/// Base URL of the production order book WebSocket API
+pub const DEFAULT_WS_URL: &'static str = "wss://api.stockfighter.io/ob/api/ws";
+
+/// Base URL of the production gamemaster API
+pub const DEFAULT_GM_URL: &'static str = "https://www.stockfighter.io/gm";
+
+/// Builds a `Stockfighter` client that talks to something other than the production API, such
+/// as a staging box or a local stand-in.
+///
+/// Plaintext `http://` and `ws://` URLs are only accepted for `localhost`, `127.0.0.1` and `[::1]`
+/// unless `allow_plaintext(true)` is set.
+///
+/// # Example
+///
+/// ```rust
+/// use stockfighter::StockfighterBuilder;
+///
+/// let sf = StockfighterBuilder::new("fake api key")
+///     .base_url("http://localhost:8000/ob/api")
+///     .ws_url("ws://localhost:8001/ob/api/ws")
+///     .build();
+/// assert!(sf.is_ok());
+/// ```
+pub struct StockfighterBuilder {
     api_key: String,
-    client: Client,
+    base_url: String,
+    ws_url: String,
+    gm_url: String,
+    allow_plaintext: bool,
+    transport: Option<Arc<Transport>>,
+    retry: RetryPolicy,
+    limiter: Option<Arc<RateLimiter>>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    max_idle_connections: usize,
+    account: Option<String>,
+    venue: Option<String>,
+    symbol: Option<String>,
+    log_requests: bool,
+    log_body_limit: usize,
+    reconnect: RetryPolicy,
+    ping_interval: Option<Duration>,
+    stale_after: Option<Duration>,
+}
+
+impl StockfighterBuilder {
+
+    pub fn new<S>(api_key: S) -> StockfighterBuilder where S: Into<String> {
+        StockfighterBuilder {
+            api_key: api_key.into(),
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            ws_url: DEFAULT_WS_URL.to_owned(),
+            gm_url: DEFAULT_GM_URL.to_owned(),
+            allow_plaintext: false,
+            transport: None,
+            retry: RetryPolicy::default(),
+            limiter: None,
+            connect_timeout: Some(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT)),
+            read_timeout: Some(Duration::from_secs(DEFAULT_READ_TIMEOUT)),
+            max_idle_connections: DEFAULT_MAX_IDLE_CONNECTIONS,
+            account: None,
+            venue: None,
+            symbol: None,
+            log_requests: false,
+            log_body_limit: DEFAULT_LOG_BODY_LIMIT,
+            reconnect: stream::default_reconnect_policy(),
+            ping_interval: Some(Duration::from_secs(stream::DEFAULT_PING_INTERVAL)),
+            stale_after: Some(Duration::from_secs(stream::DEFAULT_STALE_AFTER)),
+        }
+    }
+
+    /// REST base URL, e.g. `https://api.stockfighter.io/ob/api`
+    pub fn base_url<S>(mut self, url: S) -> StockfighterBuilder where S: Into<String> {
+        self.base_url = url.into();
+        self
+    }
+
+    /// WebSocket base URL, e.g. `wss://api.stockfighter.io/ob/api/ws`
+    pub fn ws_url<S>(mut self, url: S) -> StockfighterBuilder where S: Into<String> {
+        self.ws_url = url.into();
+        self
+    }
+
+    /// Gamemaster base URL, e.g. `https://www.stockfighter.io/gm`
+    pub fn gm_url<S>(mut self, url: S) -> StockfighterBuilder where S: Into<String> {
+        self.gm_url = url.into();
+        self
+    }
+
+    /// Account returned by `Stockfighter::default_account`
+    pub fn default_account<S>(mut self, account: S) -> StockfighterBuilder where S: Into<String> {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Venue returned by `Stockfighter::default_venue`
+    pub fn default_venue<S>(mut self, venue: S) -> StockfighterBuilder where S: Into<String> {
+        self.venue = Some(venue.into());
+        self
+    }
+
+    /// Symbol returned by `Stockfighter::default_symbol`
+    pub fn default_symbol<S>(mut self, symbol: S) -> StockfighterBuilder where S: Into<String> {
+        self.symbol = Some(symbol.into());
+        self
+    }
+
+    /// Accept `http://` and `ws://` URLs for hosts other than localhost
+    pub fn allow_plaintext(mut self, allow: bool) -> StockfighterBuilder {
+        self.allow_plaintext = allow;
+        self
+    }
+
+    /// How long to wait for a connection to the API or a WebSocket feed. `None` waits forever.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> StockfighterBuilder {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How long to wait on a REST socket read or write. `None` waits forever.
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> StockfighterBuilder {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// How many keep-alive connections to the API are kept open for reuse
+    pub fn max_idle_connections(mut self, max_idle: usize) -> StockfighterBuilder {
+        self.max_idle_connections = max_idle;
+        self
+    }
+
+    /// Log every request and response at the `trace` level: method, URL, status, latency and
+    /// bodies. The API key is never logged.
+    pub fn log_requests(mut self, log: bool) -> StockfighterBuilder {
+        self.log_requests = log;
+        self
+    }
+
+    /// How many bytes of each body `log_requests` shows before cutting it off
+    pub fn log_body_limit(mut self, limit: usize) -> StockfighterBuilder {
+        self.log_body_limit = limit;
+        self
+    }
+
+    /// Send REST requests through `transport` instead of a `HyperTransport`. The connect and
+    /// read timeouts are then up to the transport.
+    pub fn transport<T>(mut self, transport: T) -> StockfighterBuilder where T: Transport + 'static {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// How failed read-only requests are retried, see `RetryPolicy`
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> StockfighterBuilder {
+        self.retry = retry;
+        self
+    }
+
+    /// How a dropped ticker tape or executions feed reconnects. Only the delays and the number of
+    /// attempts of the policy are used. By default feeds reconnect forever, backing off to 10s.
+    pub fn reconnect_policy(mut self, reconnect: RetryPolicy) -> StockfighterBuilder {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// How often feeds ping the server, which also measures the round trip. `None` only answers
+    /// the server's pings. Every 10s by default.
+    pub fn ping_interval(mut self, interval: Option<Duration>) -> StockfighterBuilder {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// How long a feed may hear nothing before it sends `StreamEvent::Stale`. `None` never does.
+    /// 30s by default, which should be longer than the ping interval.
+    pub fn stale_after(mut self, silence: Option<Duration>) -> StockfighterBuilder {
+        self.stale_after = silence;
+        self
+    }
+
+    /// Throttle requests with `limiter`, which may be shared with other clients
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> StockfighterBuilder {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn build(self) -> Result<Stockfighter> {
+        let base_url = try!(check_url(&self.base_url, "https", "http", self.allow_plaintext));
+        let ws_url = try!(check_url(&self.ws_url, "wss", "ws", self.allow_plaintext));
+        let gm_url = try!(check_url(&self.gm_url, "https", "http", self.allow_plaintext));
+
+        let account = match self.account {
+            Some(account) => Some(try!(Account::new(account))),
+            None => None,
+        };
+        let venue = match self.venue {
+            Some(venue) => Some(try!(Venue::new(venue))),
+            None => None,
+        };
+        let symbol = match self.symbol {
+            Some(symbol) => Some(try!(Symbol::new(symbol))),
+            None => None,
+        };
+
+        let (connect_timeout, read_timeout) = (self.connect_timeout, self.read_timeout);
+        let transport: Arc<Transport> = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(HyperTransport::with_pool(self.max_idle_connections, connect_timeout, read_timeout)),
+        };
+
+        Ok(Stockfighter {
+            inner: Arc::new(Inner {
+                headers: Arc::new(vec![(AUTH_HEADER.to_owned(), self.api_key)]),
+                transport: transport,
+                retry: self.retry,
+                limiter: self.limiter,
+                connect_timeout: connect_timeout,
+                base_url: base_url,
+                ws_url: ws_url,
+                gm_url: gm_url,
+                account: account,
+                venue: venue,
+                symbol: symbol,
+                log_body_limit: if self.log_requests { Some(self.log_body_limit) } else { None },
+                reconnect: self.reconnect,
+                ping_interval: self.ping_interval,
+                stale_after: self.stale_after,
+            }),
+            deadline: None,
+        })
+    }
+}
+
+impl fmt::Debug for StockfighterBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StockfighterBuilder")
+            .field("api_key", &mask_key(&self.api_key))
+            .field("base_url", &self.base_url)
+            .field("ws_url", &self.ws_url)
+            .field("gm_url", &self.gm_url)
+            .field("account", &self.account)
+            .field("venue", &self.venue)
+            .field("symbol", &self.symbol)
+            .finish()
+    }
+}
+
+/// How many bytes of a body are logged by default, see `StockfighterBuilder::log_body_limit`
+pub const DEFAULT_LOG_BODY_LIMIT: usize = 256;
+
+/// Hides all but the last four characters of an API key, so logs can tell keys apart
+fn mask_key(key: &str) -> String {
+    let chars = key.chars().count();
+    if chars <= 8 {
+        return "****".to_owned();
+    }
+    let tail: String = key.chars().skip(chars - 4).collect();
+    format!("****{}", tail)
+}
+
+/// Cuts `body` down to at most `limit` bytes for logging, on a character boundary
+fn truncate_body(body: &str, limit: usize) -> String {
+    if body.len() <= limit {
+        return body.to_owned();
+    }
+    let mut end = limit;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}... ({} bytes)", &body[..end], body.len())
+}
+
+/// `d` in whole milliseconds
+fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + (d.subsec_nanos() / 1000000) as u64
+}
+
+/// Maps a non-200 response to an error, keeping the server's `error` message when it sent one
+fn api_error(status: u16, endpoint: &str, body: &str) -> StockfighterError {
+    let message = match serde_json::from_str::<ApiStatus>(body) {
+        Ok(ApiStatus { error: Some(message), .. }) => message,
+        _ => body.to_owned(),
+    };
+
+    match status {
+        401 | 403 => StockfighterError::Unauthorized(message),
+        404 => StockfighterError::NotFound(message),
+        429 => StockfighterError::RateLimited,
+        _ => StockfighterError::Api { status: status, endpoint: endpoint.to_owned(), message: message },
+    }
+}
+
+/// Time left until `deadline`, or `None` if it has passed
+fn remaining(deadline: Instant) -> Option<Duration> {
+    let now = Instant::now();
+    if now < deadline {
+        Some(deadline - now)
+    } else {
+        None
+    }
+}
+
+/// Validates a base URL and strips any trailing slash so paths can be appended to it
+fn check_url(url: &str, secure: &str, plaintext: &str, allow_plaintext: bool) -> Result<String> {
+    let parsed = match Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return Err(StockfighterError::InvalidUrl(url.to_owned())),
+    };
+
+    let is_local = match parsed.serialize_host() {
+        Some(ref host) => host == "localhost" || host == "127.0.0.1" || host == "[::1]",
+        None => false,
+    };
+
+    if parsed.scheme == secure || (parsed.scheme == plaintext && (is_local || allow_plaintext)) {
+        Ok(url.trim_right_matches('/').to_owned())
+    } else {
+        Err(StockfighterError::InvalidUrl(url.to_owned()))
+    }
+}
+
+/// What a REST call does, which decides whether it is retried and which rate limit budget it
+/// draws from
+#[derive(Clone, Copy, Debug)]
+enum Call {
+    /// Reading quotes, order books, heartbeats and order status. Retried.
+    Read,
+    /// Placing an order. Only retried when the retry policy opts in.
+    NewOrder,
+    /// Cancelling an order. Never retried.
+    Cancel,
+    /// Asking the gamemaster about an instance. Retried, and outside the order book's limits.
+    GamemasterRead,
+    /// Starting, stopping or resuming a level. Never retried, as a repeated start can create a
+    /// second instance, and outside the order book's limits.
+    GamemasterAction,
+}
+
+impl Call {
+    fn retried(self, retry: &RetryPolicy) -> bool {
+        match self {
+            Call::Read | Call::GamemasterRead => true,
+            Call::NewOrder => retry.retries_new_orders(),
+            Call::Cancel | Call::GamemasterAction => false,
+        }
+    }
+
+    fn budget(self) -> Option<RequestKind> {
+        match self {
+            Call::Read => Some(RequestKind::Read),
+            Call::NewOrder | Call::Cancel => Some(RequestKind::OrderEntry),
+            Call::GamemasterRead | Call::GamemasterAction => None,
+        }
+    }
+}
+
+/// Client for the Stockfighter API
+///
+/// A `Stockfighter` is cheap to clone and can be shared between threads: clones share the same
+/// settings and the same pool of keep-alive connections.
+#[derive(Clone)]
+pub struct Stockfighter {
+    inner: Arc<Inner>,
+    deadline: Option<Duration>,
+}
+
+// Everything that is the same for all clones of a `Stockfighter`
+#[derive(Clone)]
+struct Inner {
+    // The auth header is built once and shared by every request
+    headers: Arc<Vec<(String, String)>>,
+    transport: Arc<Transport>,
+    retry: RetryPolicy,
+    limiter: Option<Arc<RateLimiter>>,
+    connect_timeout: Option<Duration>,
+    base_url: String,
+    ws_url: String,
+    gm_url: String,
+    account: Option<Account>,
+    venue: Option<Venue>,
+    symbol: Option<Symbol>,
+    // Set when requests are logged
+    log_body_limit: Option<usize>,
+    reconnect: RetryPolicy,
+    ping_interval: Option<Duration>,
+    stale_after: Option<Duration>,
+}
+
+impl fmt::Debug for Stockfighter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let api_key = self.inner.headers.iter()
+            .find(|&&(ref name, _)| name == AUTH_HEADER)
+            .map_or(String::new(), |&(_, ref key)| mask_key(key));
+
+        f.debug_struct("Stockfighter")
+            .field("api_key", &api_key)
+            .field("base_url", &self.inner.base_url)
+            .field("ws_url", &self.inner.ws_url)
+            .field("gm_url", &self.inner.gm_url)
+            .field("account", &self.inner.account)
+            .field("venue", &self.inner.venue)
+            .field("symbol", &self.inner.symbol)
+            .field("retry", &self.inner.retry)
+            .field("deadline", &self.deadline)
+            .finish()
+    }
 }
 
 impl Stockfighter {
 
+    /// Create a client for the production Stockfighter API
     pub fn new<S>(api_key: S) -> Stockfighter where S: Into<String> {
-        Stockfighter { api_key: api_key.into(), client: Client::new() }
+        StockfighterBuilder::new(api_key).build().expect("default URLs are valid")
     }
 
-    /// Check that the Stockfighter API is up
+    /// Start building a client with non-default settings
+    pub fn builder<S>(api_key: S) -> StockfighterBuilder where S: Into<String> {
+        StockfighterBuilder::new(api_key)
+    }
+
+    /// Account set with `StockfighterBuilder::default_account`, or loaded from the environment
+    /// or a config file
+    pub fn default_account(&self) -> Option<&Account> {
+        self.inner.account.as_ref()
+    }
+
+    /// Venue set with `StockfighterBuilder::default_venue`, or loaded from the environment or a
+    /// config file
+    pub fn default_venue(&self) -> Option<&Venue> {
+        self.inner.venue.as_ref()
+    }
+
+    /// Symbol set with `StockfighterBuilder::default_symbol`
+    pub fn default_symbol(&self) -> Option<&Symbol> {
+        self.inner.symbol.as_ref()
+    }
+
+    /// A copy of this client with other defaults, sharing its connections and settings
+    fn with_defaults(&self, account: Account, venue: Venue, symbol: Option<Symbol>) -> Stockfighter {
+        let mut inner = (*self.inner).clone();
+        inner.account = Some(account);
+        inner.venue = Some(venue);
+        inner.symbol = symbol;
+        Stockfighter { inner: Arc::new(inner), deadline: self.deadline }
+    }
+
+    /// A copy of this client whose calls, including any retries, fail with
+    /// `StockfighterError::Timeout` once `deadline` has passed
+    ///
+    /// With the default transport the calls reuse the client's pooled connections. Socket reads
+    /// and writes are cut short at the deadline, but opening a new connection is only bounded by
+    /// the builder's `connect_timeout`.
     ///
     /// # Example
     ///
-    /// ```rust
+    /// ```no_run
+    /// use std::time::Duration;
     /// use stockfighter::Stockfighter;
     ///
     /// let sf = Stockfighter::new("fake api key");
-    /// assert!(sf.heartbeat().is_ok());
+    /// let quote = sf.with_deadline(Duration::from_millis(250)).quote("TESTEX", "FOOBAR");
     /// ```
-    pub fn heartbeat(&self) -> Result<()> {
-        let mut res = try!(self.client
-            .get("https://api.stockfighter.io/ob/api/heartbeat")
-            .send());
+    pub fn with_deadline(&self, deadline: Duration) -> Stockfighter {
+        Stockfighter { inner: self.inner.clone(), deadline: Some(deadline) }
+    }
+
+    /// Send a request to `endpoint` (relative to the REST base URL) and decode the response.
+    ///
+    /// `call` decides whether a failed request is retried according to the retry policy, and
+    /// which rate limit budget it draws from.
+    fn request<T>(&self, call: Call, method: Method, endpoint: &str, body: Option<&str>) -> Result<T>
+        where T: DeserializeOwned {
 
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiDown);
+        self.request_to(&self.inner.base_url, call, method, endpoint, body)
+    }
+
+    /// Send a request to `endpoint` relative to `base`, which is the REST base URL for everything
+    /// but the gamemaster, see `request`
+    fn request_to<T>(&self, base: &str, call: Call, method: Method, endpoint: &str, body: Option<&str>) -> Result<T>
+        where T: DeserializeOwned {
+
+        let retry = call.retried(&self.inner.retry);
+
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+
+        let mut attempt = 1;
+        loop {
+            match self.request_once(base, call, method, endpoint, body, deadline) {
+                Err(ref err) if retry && attempt < self.inner.retry.attempts() && self.inner.retry.is_retryable(err) => {
+                    let delay = self.inner.retry.delay(attempt);
+                    if let Some(deadline) = deadline {
+                        if remaining(deadline).map_or(true, |left| left <= delay) {
+                            return Err(StockfighterError::Timeout);
+                        }
+                    }
+                    debug!("Attempt {} of {} {} failed ({}), retrying in {:?}", attempt, method, endpoint, err, delay);
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
         }
+    }
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
+    /// Send a single request without retrying, failing with `StockfighterError::Timeout` if it
+    /// cannot be sent and answered before `deadline`.
+    ///
+    /// Non-200 responses and `"ok": false` bodies are turned into the matching `StockfighterError`,
+    /// keeping the `error` message the server sent.
+    fn request_once<T>(&self, base: &str, call: Call, method: Method, endpoint: &str, body: Option<&str>,
+                       deadline: Option<Instant>) -> Result<T>
+        where T: DeserializeOwned {
+
+        if let (Some(limiter), Some(kind)) = (self.inner.limiter.as_ref(), call.budget()) {
+            try!(limiter.acquire_before(kind, deadline));
+        }
+
+        // Measured after the limiter, which may have waited for a token
+        let timeout = match deadline {
+            Some(deadline) => match remaining(deadline) {
+                Some(timeout) => Some(timeout),
+                None => return Err(StockfighterError::Timeout),
+            },
+            None => None,
+        };
+
+        let request = Request {
+            method: method,
+            url: format!("{}{}", base, endpoint),
+            headers: self.inner.headers.clone(),
+            body: body.map(|body| body.to_owned()),
+            timeout: timeout,
+        };
+
+        let res = match self.inner.log_body_limit {
+            Some(limit) => try!(self.send_logged(&request, limit)),
+            None => try!(self.inner.transport.send(&request)),
+        };
+
+        if res.status != 200 {
+            return Err(api_error(res.status, endpoint, &res.body));
+        }
 
-        let hb: Heartbeat = try!(json::decode(&body));
+        let body = res.body;
+        let status = try!(serde_json::from_str::<ApiStatus>(&body));
 
-        match hb.ok {
-            true => Ok(()),
-            false => Err(StockfighterError::ApiDown)
+        if status.ok == Some(false) {
+            return Err(StockfighterError::Api {
+                status: res.status,
+                endpoint: endpoint.to_owned(),
+                message: status.error.unwrap_or_default(),
+            });
         }
+
+        Ok(try!(serde_json::from_str::<T>(&body)))
     }
 
-    /// Check that a specific venue is up
+    /// Send `request`, logging it and the response with bodies cut off at `limit` bytes
+    fn send_logged(&self, request: &Request, limit: usize) -> Result<transport::Response> {
+        trace!("--> {} {} headers={:?} body={}", request.method, request.url, request.redacted_headers(),
+               request.body.as_ref().map_or(String::new(), |body| truncate_body(body, limit)));
+
+        let start = Instant::now();
+        let result = self.inner.transport.send(request);
+        let latency = millis(start.elapsed());
+
+        match result {
+            Ok(ref res) => trace!("<-- {} {} {} in {}ms body={}", res.status, request.method, request.url,
+                                  latency, truncate_body(&res.body, limit)),
+            Err(ref err) => trace!("<-- {} {} failed in {}ms: {}", request.method, request.url, latency, err),
+        }
+        result
+    }
+
+    /// Check that the Stockfighter API is up
     ///
     /// # Example
     ///
@@ -323,26 +967,36 @@ impl Stockfighter {
     /// use stockfighter::Stockfighter;
     ///
     /// let sf = Stockfighter::new("fake api key");
-    /// assert!(sf.venue_heartbeat("TESTEX").is_ok());
+    /// assert!(sf.heartbeat().is_ok());
     /// ```
-    pub fn venue_heartbeat(&self, venue: &str) -> Result<()> {
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/heartbeat", venue);
-        let mut res = try!(self.client
-            .get(&url)
-            .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::VenueDown(venue.to_owned()));
+    pub fn heartbeat(&self) -> Result<()> {
+        match self.request::<Heartbeat>(Call::Read, Method::Get, "/heartbeat", None) {
+            Ok(_) => Ok(()),
+            // Either a server error or a 200 saying "ok": false
+            Err(StockfighterError::Api { message, .. }) => Err(StockfighterError::ApiDown(message)),
+            Err(err) => Err(err),
         }
+    }
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-
-        let hb: VenueHeartbeat = try!(json::decode(&body));
-
-        match hb.ok {
-            true => Ok(()),
-            false => Err(StockfighterError::VenueDown(venue.to_owned()))
+    /// Check that a specific venue is up
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use stockfighter::Stockfighter;
+    ///
+    /// let sf = Stockfighter::new("fake api key");
+    /// assert!(sf.venue_heartbeat("TESTEX").is_ok());
+    /// ```
+    pub fn venue_heartbeat<V>(&self, venue: V) -> Result<()> where V: IntoChecked<Venue> {
+        let venue = try!(venue.into_checked());
+        let endpoint = format!("/venues/{}/heartbeat", venue);
+
+        match self.request::<VenueHeartbeat>(Call::Read, Method::Get, &endpoint, None) {
+            Ok(_) => Ok(()),
+            Err(StockfighterError::NotFound(_)) | Err(StockfighterError::Api { .. }) =>
+                Err(StockfighterError::VenueDown(venue.into())),
+            Err(err) => Err(err),
         }
     }
 
@@ -356,28 +1010,12 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.quote("TESTEX", "FOOBAR").is_ok());
     /// ```
-    pub fn quote(&self, venue: &str, stock: &str) -> Result<Quote> {
-
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks/{}/quote", venue, stock);
-
-        let mut res = try!(self.client
-            .get(&url)
-            .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-            .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiError);
-        }
+    pub fn quote<V, S>(&self, venue: V, stock: S) -> Result<Quote>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-
-        let quote = try!(json::decode::<Quote>(&body));
-
-        match quote.ok {
-            true => Ok(quote),
-            false => Err(StockfighterError::ApiError)
-        }
+        let (venue, stock) = (try!(venue.into_checked()), try!(stock.into_checked()));
+        let endpoint = format!("/venues/{}/stocks/{}/quote", venue, stock);
+        self.request(Call::Read, Method::Get, &endpoint, None)
     }
 
     /// List the stocks available for trading on a venue
@@ -390,134 +1028,175 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.stocks_on_a_venue("TESTEX").is_ok());
     /// ```
-    pub fn stocks_on_a_venue(&self, venue: &str) -> Result<StockList> {
+    pub fn stocks_on_a_venue<V>(&self, venue: V) -> Result<StockList> where V: IntoChecked<Venue> {
+        let venue = try!(venue.into_checked());
+        let endpoint = format!("/venues/{}/stocks", venue);
 
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks", venue );
+        match self.request(Call::Read, Method::Get, &endpoint, None) {
+            Err(StockfighterError::NotFound(_)) => Err(StockfighterError::VenueDown(venue.into())),
+            result => result,
+        }
+    }
 
-        let mut res = try!(self.client
-            .get(&url)
-            .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-            .send());
+    /// Call `cb` with every quote on a venue's ticker tape
+    ///
+    /// A dropped connection is reconnected according to the reconnect policy. `cb` is told about
+    /// the gap with `StreamEvent::Disconnected` and `StreamEvent::Reconnected`. `cb` is handed
+    /// back by `SubscriptionHandle::stop`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use stockfighter::{Stockfighter, StreamEvent};
+    ///
+    /// let sf = Stockfighter::new("fake api key");
+    /// let handle = sf.ticker_tape_venue_with("EXB123456", "TESTEX", |event| match event {
+    ///     StreamEvent::Message(quote) => println!("{:?}", quote),
+    ///     StreamEvent::Reconnected { downtime } => println!("missed {:?} of quotes", downtime),
+    ///     _ => (),
+    /// });
+    /// ```
+    pub fn ticker_tape_venue_with<A, V, F>(&self, account: A, venue: V, cb: F) -> Result<SubscriptionHandle<F>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>,
+              F: Send + 'static + FnMut(StreamEvent<TickerTapeQuote>) {
 
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::VenueDown(venue.to_owned()));
-        }
+        let url = try!(self.feed_url("tickertape", account, venue, None::<Symbol>));
+        self.subscribe(url, stream::decode_quote, cb, |cb, event| cb(event))
+    }
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
+    pub fn ticker_tape_venue_stock_with<A, V, S, F>(&self, account: A, venue: V, stock: S, cb: F)
+                                                    -> Result<SubscriptionHandle<F>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              F: Send + 'static + FnMut(StreamEvent<TickerTapeQuote>) {
 
-        let stocklist = try!(json::decode::<StockList>(&body));
+        let url = try!(self.feed_url("tickertape", account, venue, Some(stock)));
+        self.subscribe(url, stream::decode_quote, cb, |cb, event| cb(event))
+    }
 
-        match stocklist.ok {
-            true => Ok(stocklist),
-            false => Err(StockfighterError::ApiError)
-        }
+    pub fn executions_venue_with<A, V, F>(&self, account: A, venue: V, cb: F) -> Result<SubscriptionHandle<F>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>,
+              F: Send + 'static + FnMut(StreamEvent<ExecutionOrdersStatuses>) {
+
+        let url = try!(self.feed_url("executions", account, venue, None::<Symbol>));
+        self.subscribe(url, stream::decode_execution, cb, |cb, event| cb(event))
     }
 
-    fn ticker_tape<F>(&self, url: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(TickerTapeQuote) {
+    pub fn executions_venue_stock_with<A, V, S, F>(&self, account: A, venue: V, stock: S, cb: F)
+                                                   -> Result<SubscriptionHandle<F>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              F: Send + 'static + FnMut(StreamEvent<ExecutionOrdersStatuses>) {
 
-        let wss = Url::parse(&url).unwrap();
+        let url = try!(self.feed_url("executions", account, venue, Some(stock)));
+        self.subscribe(url, stream::decode_execution, cb, |cb, event| cb(event))
+    }
 
-        let request = try!(WSClient::connect(&wss));
-        let response = try!(request.send());
-        try!(response.validate());
+    /// Like `ticker_tape_venue_with`, but `cb` also gets `state`, which `SubscriptionHandle::stop`
+    /// hands back. A local order book or counters can live in `state` without any locking.
+    pub fn ticker_tape_venue_with_state<A, V, T, F>(&self, account: A, venue: V, state: T, cb: F)
+                                                    -> Result<SubscriptionHandle<T>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>,
+              T: Send + 'static, F: Send + 'static + FnMut(&mut T, StreamEvent<TickerTapeQuote>) {
 
-        let (mut sender, mut receiver) = response.begin().split();
+        let url = try!(self.feed_url("tickertape", account, venue, None::<Symbol>));
+        self.subscribe(url, stream::decode_quote, state, cb)
+    }
 
-        let handle = thread::spawn(move || {
-            trace!("Spawned thread for ticker tape websocket");
-            for message in receiver.incoming_messages() {
-                let message: Message = message.unwrap();
-                trace!("Received message {:?} from ticker tape websocket", message);
+    pub fn ticker_tape_venue_stock_with_state<A, V, S, T, F>(&self, account: A, venue: V, stock: S, state: T, cb: F)
+                                                             -> Result<SubscriptionHandle<T>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              T: Send + 'static, F: Send + 'static + FnMut(&mut T, StreamEvent<TickerTapeQuote>) {
 
-                match message.opcode {
-                    Type::Text => {
-                        let response = std::str::from_utf8(&*message.payload).unwrap();
-                        debug!("Valid test response {} from ticker tape websocket", &response);
-                        let tt_quote = json::decode::<TickerTape>(&response).unwrap();
-                        cb(tt_quote.quote);
-                    }
-                    Type::Close => {
-                        let _ = sender.send_message(&Message::close());
-                        break;
-                    }
-                    Type::Ping => {
-                        sender.send_message(&Message::pong(message.payload)).unwrap();
-                    }
-                    _ => (),
-                }
-            }
-        });
+        let url = try!(self.feed_url("tickertape", account, venue, Some(stock)));
+        self.subscribe(url, stream::decode_quote, state, cb)
+    }
 
-        Ok(handle)
+    pub fn executions_venue_with_state<A, V, T, F>(&self, account: A, venue: V, state: T, cb: F)
+                                                   -> Result<SubscriptionHandle<T>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>,
+              T: Send + 'static, F: Send + 'static + FnMut(&mut T, StreamEvent<ExecutionOrdersStatuses>) {
+
+        let url = try!(self.feed_url("executions", account, venue, None::<Symbol>));
+        self.subscribe(url, stream::decode_execution, state, cb)
     }
 
-    pub fn ticker_tape_venue_with<F>(&self, account: &str, venue: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(TickerTapeQuote) {
+    pub fn executions_venue_stock_with_state<A, V, S, T, F>(&self, account: A, venue: V, stock: S, state: T, cb: F)
+                                                            -> Result<SubscriptionHandle<T>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              T: Send + 'static, F: Send + 'static + FnMut(&mut T, StreamEvent<ExecutionOrdersStatuses>) {
 
-        let url = format!("wss://api.stockfighter.io/ob/api/ws/{}/venues/{}/tickertape", account, venue);
-        self.ticker_tape(&url, cb)
+        let url = try!(self.feed_url("executions", account, venue, Some(stock)));
+        self.subscribe(url, stream::decode_execution, state, cb)
     }
 
-    pub fn ticker_tape_venue_stock_with<F>(&self, account: &str, venue: &str, stock: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(TickerTapeQuote) {
+    /// Subscribe to the quotes on a venue's ticker tape, see `Subscription`
+    pub fn ticker_tape_venue<A, V>(&self, account: A, venue: V) -> Result<Subscription<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
 
-        let url = format!("wss://api.stockfighter.io/ob/api/ws/{}/venues/{}/tickertape/stocks/{}", account, venue, stock);
-        self.ticker_tape(&url, cb)
+        let url = try!(self.feed_url("tickertape", account, venue, None::<Symbol>));
+        self.subscription(url, stream::decode_quote)
     }
 
-    fn executions<F>(&self, url: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(ExecutionOrdersStatuses) {
+    /// Subscribe to the quotes for one stock on a venue's ticker tape
+    pub fn ticker_tape_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S) -> Result<Subscription<TickerTapeQuote>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-        let wss = Url::parse(&url).unwrap();
+        let url = try!(self.feed_url("tickertape", account, venue, Some(stock)));
+        self.subscription(url, stream::decode_quote)
+    }
 
-        let request = try!(WSClient::connect(&wss));
-        let response = try!(request.send());
-        try!(response.validate());
+    /// Subscribe to the executions of an account's orders on a venue
+    pub fn executions_venue<A, V>(&self, account: A, venue: V) -> Result<Subscription<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue> {
 
-        let (mut sender, mut receiver) = response.begin().split();
+        let url = try!(self.feed_url("executions", account, venue, None::<Symbol>));
+        self.subscription(url, stream::decode_execution)
+    }
 
-        let handle = thread::spawn(move || {
-            trace!("Spawned thread for executions websocket");
-            for message in receiver.incoming_messages() {
-                let message: Message = message.unwrap();
-                trace!("Received message {:?} from executions websocket", message);
+    /// Subscribe to the executions of an account's orders for one stock on a venue
+    pub fn executions_venue_stock<A, V, S>(&self, account: A, venue: V, stock: S)
+                                           -> Result<Subscription<ExecutionOrdersStatuses>>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-                match message.opcode {
-                    Type::Text => {
-                        let response = std::str::from_utf8(&*message.payload).unwrap();
-                        debug!("Valid test response {} from executions websocket", &response);
-                        let status = json::decode::<ExecutionOrdersStatuses>(&response).unwrap();
-                        cb(status);
-                    }
-                    Type::Close => {
-                        let _ = sender.send_message(&Message::close());
-                        break;
-                    }
-                    Type::Ping => {
-                        sender.send_message(&Message::pong(message.payload)).unwrap();
-                    }
-                    _ => (),
-                }
-            }
-        });
+        let url = try!(self.feed_url("executions", account, venue, Some(stock)));
+        self.subscription(url, stream::decode_execution)
+    }
+
+    /// URL of the `feed` ("tickertape" or "executions") of an account on a venue, optionally
+    /// narrowed down to one stock
+    fn feed_url<A, V, S>(&self, feed: &str, account: A, venue: V, stock: Option<S>) -> Result<String>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-        Ok(handle)
+        let (account, venue) = (try!(account.into_checked()), try!(venue.into_checked()));
+        match stock {
+            Some(stock) => {
+                let stock = try!(stock.into_checked());
+                Ok(format!("{}/{}/venues/{}/{}/stocks/{}", self.inner.ws_url, account, venue, feed, stock))
+            }
+            None => Ok(format!("{}/{}/venues/{}/{}", self.inner.ws_url, account, venue, feed)),
+        }
     }
 
-    pub fn executions_venue_with<F>(&self, account: &str, venue: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(ExecutionOrdersStatuses) {
+    fn subscription<T>(&self, url: String, decode: fn(&str) -> serde_json::Result<T>) -> Result<Subscription<T>>
+        where T: Send + 'static {
 
-        let url = format!("wss://api.stockfighter.io/ob/api/ws/{}/venues/{}/executions", account, venue);
-        self.executions(&url, cb)
+        stream::subscription(url, self.feed_config(), decode)
     }
 
-    pub fn executions_venue_stock_with<F>(&self, account: &str, venue: &str, stock: &str, cb: F) -> Result<thread::JoinHandle<()>>
-        where F: Send + 'static + Fn(ExecutionOrdersStatuses) {
+    /// Run a feed that reconnects according to the reconnect policy, see `stream::spawn`
+    fn subscribe<T, S, F>(&self, url: String, decode: fn(&str) -> serde_json::Result<T>, state: S, cb: F)
+                          -> Result<SubscriptionHandle<S>>
+        where T: 'static, S: Send + 'static, F: Send + 'static + FnMut(&mut S, StreamEvent<T>) {
+
+        stream::spawn(url, self.feed_config(), decode, state, cb)
+    }
 
-        let url = format!("wss://api.stockfighter.io/ob/api/ws/{}/venues/{}/executions/stocks/{}", account, venue, stock);
-        self.executions(&url, cb)
+    fn feed_config(&self) -> FeedConfig {
+        FeedConfig {
+            connect_timeout: self.inner.connect_timeout,
+            reconnect: self.inner.reconnect.clone(),
+            ping_interval: self.inner.ping_interval,
+            stale_after: self.inner.stale_after,
+        }
     }
 
     /// Get the orderbook for a particular stock
@@ -530,31 +1209,23 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.orderbook_for_stock("TESTEX", "FOOBAR").is_ok());
     /// ```
-    pub fn orderbook_for_stock(&self, venue: &str, symbol: &str) -> Result<OrderbookList> {
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks/{}", venue, symbol);
-
-        let mut res = try!(self.client
-            .get(&url)
-            .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-            .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiError);
-        }
+    pub fn orderbook_for_stock<V, S>(&self, venue: V, symbol: S) -> Result<OrderbookList>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-
-        let orderbook = try!(json::decode::<OrderbookList>(&body));
-
-        match orderbook.ok {
-            true => Ok(orderbook),
-            false => Err(StockfighterError::ApiError)
-        }
+        let (venue, symbol) = (try!(venue.into_checked()), try!(symbol.into_checked()));
+        let endpoint = format!("/venues/{}/stocks/{}", venue, symbol);
+        self.request(Call::Read, Method::Get, &endpoint, None)
     }
 
     /// Post a new order
     ///
+    /// **`price` is in cents when it is a number but in dollars when it is a string**, so `50` is
+    /// 50 cents while `"50"` is $50.00. Pass a `Price`, e.g. `Price::from_cents(5000)`, to make
+    /// the unit explicit.
+    ///
+    /// An order that fails with `StockfighterError::Timeout` may still have reached the venue, so
+    /// look for it with `status_for_all_orders` before placing it again.
+    ///
     /// # Example
     /// # Note that this tests for failure, due to the fake api
     /// key. With a real key, this example should pass.
@@ -563,63 +1234,32 @@ impl Stockfighter {
     ///
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.new_order("EXB123456", "TESTEX", "FOOBAR", 10000, 42,
-    ///                                 OrderDirection::buy, OrderType::Limit).is_err());
+    ///                                 OrderDirection::Buy, OrderType::Limit).is_err());
     /// ```
-    pub fn new_order(&self, account: &str, venue: &str, stock: &str, price: usize, qty: usize,
-                     direction: OrderDirection, order_type: OrderType) -> Result<OrderStatus> {
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks/{}/orders", venue, stock);
-
-        let ot = match order_type {
-            OrderType::Limit => "limit",
-            OrderType::Market => "market",
-            OrderType::FillOrKill => "fill-or-kill",
-            OrderType::ImmediateOrCancel => "immediate-or-cancel"
-        }.to_string();
-        let order = Order {account: account.to_string(), venue: venue.to_string(), stock: stock.to_string(),
-                           price: price, qty: qty, direction: direction, orderType: ot};
-        let order_encoded = try!(json::encode(&order)).to_string();
-        let mut res = try!(
-            self.client
-                .post(&url)
-                .header(XStarfighterAuthorization(self.api_key.clone()))
-                .body(&order_encoded)
-                .send()
-        );
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiError);
-        }
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-        let order_status = try!(json::decode::<OrderStatus>(&body));
-        match order_status.ok {
-            true => Ok(order_status),
-            false => Err(StockfighterError::ApiError)
-        }
-    }
+    pub fn new_order<A, V, S, P, Q>(&self, account: A, venue: V, stock: S, price: P, qty: Q,
+                                    direction: OrderDirection, order_type: OrderType) -> Result<OrderStatus>
+        where A: IntoChecked<Account>, V: IntoChecked<Venue>, S: IntoChecked<Symbol>,
+              P: IntoChecked<Price>, Q: IntoChecked<Qty> {
 
-    pub fn existing_order_status(&self, id: usize, venue: &str, stock: &str) -> Result<OrderStatus> {
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks/{}/orders/{}", venue, stock, id);
+        let (account, venue) = (try!(account.into_checked()), try!(venue.into_checked()));
+        let stock = try!(stock.into_checked());
+        let (price, qty) = (try!(price.into_checked()), try!(qty.into_checked()));
+        let endpoint = format!("/venues/{}/stocks/{}/orders", venue, stock);
 
-        let mut res = try!(
-            self.client
-                .get(&url)
-                .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix clone here.
-                .send()
-        );
+        let order = Order {account: account.into(), venue: venue.into(), stock: stock.into(),
+                           price: price, qty: qty.into(), direction: direction, order_type: order_type};
+        let order_encoded = try!(serde_json::to_string(&order));
 
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiError);
-        }
-
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
+        self.request(Call::NewOrder, Method::Post, &endpoint, Some(&order_encoded))
+    }
 
-        let order_status = try!(json::decode::<OrderStatus>(&body));
+    pub fn existing_order_status<I, V, S>(&self, id: I, venue: V, stock: S) -> Result<OrderStatus>
+        where I: IntoChecked<OrderId>, V: IntoChecked<Venue>, S: IntoChecked<Symbol> {
 
-        match order_status.ok {
-            true => Ok(order_status),
-            false => Err(StockfighterError::ApiError)
-        }
+        let id = try!(id.into_checked());
+        let (venue, stock) = (try!(venue.into_checked()), try!(stock.into_checked()));
+        let endpoint = format!("/venues/{}/stocks/{}/orders/{}", venue, stock, id);
+        self.request(Call::Read, Method::Get, &endpoint, None)
     }
 
     /// [Get the Status For All Orders](https://starfighter.readme.io/docs/status-for-all-orders)
@@ -632,28 +1272,12 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.status_for_all_orders("TESTEX", "EXB123456").is_ok());
     /// ```
-    pub fn status_for_all_orders(&self, venue: &str, account: &str) -> Result<StockOrdersStatuses> {
-
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/accounts/{}/orders", venue, account);
-
-        let mut res = try!(self.client
-                           .get(&url)
-                           .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-                           .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::VenueDown(venue.to_owned()));
-        }
+    pub fn status_for_all_orders<V, A>(&self, venue: V, account: A) -> Result<StockOrdersStatuses>
+        where V: IntoChecked<Venue>, A: IntoChecked<Account> {
 
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
-
-        let stock_statuses = try!(json::decode::<StockOrdersStatuses>(&body));
-
-        match stock_statuses.ok {
-            true => Ok(stock_statuses),
-            false => Err(StockfighterError::ApiError)
-        }
+        let (venue, account) = (try!(venue.into_checked()), try!(account.into_checked()));
+        let endpoint = format!("/venues/{}/accounts/{}/orders", venue, account);
+        self.request(Call::Read, Method::Get, &endpoint, None)
     }
 
     /// [Get the Status For All Orders In A Stock](https://starfighter.readme.io/docs/status-for-all-orders-in-a-stock)
@@ -666,28 +1290,13 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.status_for_all_orders_on_a_stock("TESTEX", "EXB123456", "FOOBAR").is_ok());
     /// ```
-    pub fn status_for_all_orders_on_a_stock(&self, venue: &str, account: &str, stock: &str) -> Result<StockOrdersStatuses> {
-
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/accounts/{}/stocks/{}/orders", venue, account, stock );
-
-        let mut res = try!(self.client
-                           .get(&url)
-                           .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-                           .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::VenueDown(venue.to_owned()));
-        }
-
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
+    pub fn status_for_all_orders_on_a_stock<V, A, S>(&self, venue: V, account: A, stock: S) -> Result<StockOrdersStatuses>
+        where V: IntoChecked<Venue>, A: IntoChecked<Account>, S: IntoChecked<Symbol> {
 
-        let stock_statuses = try!(json::decode::<StockOrdersStatuses>(&body));
-
-        match stock_statuses.ok {
-            true => Ok(stock_statuses),
-            false => Err(StockfighterError::ApiError)
-        }
+        let (venue, account) = (try!(venue.into_checked()), try!(account.into_checked()));
+        let stock = try!(stock.into_checked());
+        let endpoint = format!("/venues/{}/accounts/{}/stocks/{}/orders", venue, account, stock);
+        self.request(Call::Read, Method::Get, &endpoint, None)
     }
 
     /// [Cancel An Order](https://starfighter.readme.io/docs/cancel-an-order)
@@ -700,26 +1309,12 @@ impl Stockfighter {
     /// let sf = Stockfighter::new("fake api key");
     /// assert!(sf.cancel_an_order("TESTEX", "FOOBAR", 1).is_ok());
     /// ```
-    pub fn cancel_an_order(&self, venue: &str, stock: &str, order: usize) -> Result<OrderStatus> {
-        let url = format!("https://api.stockfighter.io/ob/api/venues/{}/stocks/{}/orders/{}", venue, stock, order );
-
-        let mut res = try!(self.client
-        .delete(&url)
-        .header(XStarfighterAuthorization(self.api_key.clone())) // TODO fix the use of clone here
-        .send());
-
-        if res.status != StatusCode::Ok {
-            return Err(StockfighterError::ApiError);
-        }
-
-        let mut body = String::new();
-        try!(res.read_to_string(&mut body));
+    pub fn cancel_an_order<V, S, I>(&self, venue: V, stock: S, order: I) -> Result<OrderStatus>
+        where V: IntoChecked<Venue>, S: IntoChecked<Symbol>, I: IntoChecked<OrderId> {
 
-        let order_status = try!(json::decode::<OrderStatus>(&body));
-
-        match order_status.ok {
-            true => Ok(order_status),
-            false => Err(StockfighterError::ApiError)
-        }
+        let (venue, stock) = (try!(venue.into_checked()), try!(stock.into_checked()));
+        let order = try!(order.into_checked());
+        let endpoint = format!("/venues/{}/stocks/{}/orders/{}", venue, stock, order);
+        self.request(Call::Cancel, Method::Delete, &endpoint, None)
     }
 }
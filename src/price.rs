@@ -0,0 +1,172 @@
+//! Prices in cents
+
+use std::fmt;
+use std::result;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+use {Result, StockfighterError};
+
+/// A price, stored as a whole number of cents like the API sends it
+///
+/// Arithmetic is checked, so subtracting a spread from a price near zero gives `None` instead of
+/// wrapping around. `Display` shows dollars, and `FromStr` accepts `"$12.34"`, `"12.34"` or `"12"`.
+///
+/// # Example
+///
+/// ```rust
+/// use stockfighter::Price;
+///
+/// let bid: Price = "$51.00".parse().unwrap();
+/// let spread = Price::from_cents(25);
+///
+/// assert_eq!(bid.checked_add(spread), Some(Price::from_cents(5125)));
+/// assert_eq!(spread.checked_sub(bid), None);
+/// assert_eq!(bid.offset_bps(-10), Some(Price::from_cents(5095)));
+/// assert_eq!(Price::from_cents(5137).round_to_tick(Price::from_cents(5)), Price::from_cents(5135));
+/// assert_eq!(bid.to_string(), "$51.00");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Price(usize);
+
+impl Price {
+
+    pub fn from_cents(cents: usize) -> Price {
+        Price(cents)
+    }
+
+    /// Price for a whole number of dollars, or `None` on overflow
+    pub fn from_dollars(dollars: usize) -> Option<Price> {
+        dollars.checked_mul(100).map(Price)
+    }
+
+    pub fn cents(&self) -> usize {
+        self.0
+    }
+
+    /// The price in dollars, for display and statistics only
+    pub fn dollars(&self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+
+    pub fn checked_add(self, other: Price) -> Option<Price> {
+        self.0.checked_add(other.0).map(Price)
+    }
+
+    pub fn checked_sub(self, other: Price) -> Option<Price> {
+        self.0.checked_sub(other.0).map(Price)
+    }
+
+    /// Subtract, stopping at zero
+    pub fn saturating_sub(self, other: Price) -> Price {
+        Price(self.0.saturating_sub(other.0))
+    }
+
+    pub fn checked_mul(self, qty: usize) -> Option<Price> {
+        self.0.checked_mul(qty).map(Price)
+    }
+
+    /// Round to the nearest multiple of `tick`, halves rounding up. A zero tick leaves the price as is.
+    pub fn round_to_tick(self, tick: Price) -> Price {
+        if tick.0 == 0 {
+            return self;
+        }
+        let down = self.round_down_to_tick(tick);
+        if self.0 - down.0 >= tick.0 - tick.0 / 2 {
+            down.checked_add(tick).unwrap_or(down)
+        } else {
+            down
+        }
+    }
+
+    /// Round down to a multiple of `tick`, e.g. for the price of a bid
+    pub fn round_down_to_tick(self, tick: Price) -> Price {
+        if tick.0 == 0 {
+            return self;
+        }
+        Price(self.0 - self.0 % tick.0)
+    }
+
+    /// Round up to a multiple of `tick`, e.g. for the price of an offer
+    pub fn round_up_to_tick(self, tick: Price) -> Option<Price> {
+        if tick.0 == 0 || self.0 % tick.0 == 0 {
+            return Some(self);
+        }
+        self.round_down_to_tick(tick).checked_add(tick)
+    }
+
+    /// The price moved by `bps` basis points (hundredths of a percent), rounded to the nearest
+    /// cent. `None` if the result would be negative or overflow.
+    pub fn offset_bps(self, bps: i64) -> Option<Price> {
+        let factor = 10000i64.checked_add(bps);
+        let scaled = factor.and_then(|factor| (self.0 as i64).checked_mul(factor));
+        match scaled {
+            Some(scaled) if scaled >= 0 => Some(Price(((scaled + 5000) / 10000) as usize)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${}.{:02}", self.0 / 100, self.0 % 100)
+    }
+}
+
+impl FromStr for Price {
+    type Err = StockfighterError;
+
+    fn from_str(s: &str) -> Result<Price> {
+        let invalid = || StockfighterError::InvalidArgument(format!("price {:?} is not a dollar amount", s));
+
+        let amount = s.trim();
+        let amount = if amount.starts_with('$') { &amount[1..] } else { amount };
+
+        let (dollars, cents) = match amount.find('.') {
+            Some(dot) => (&amount[..dot], &amount[dot + 1..]),
+            None => (amount, ""),
+        };
+
+        if dollars.is_empty() || cents.len() > 2
+            || !dollars.chars().chain(cents.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let dollars = try!(dollars.parse::<usize>().map_err(|_| invalid()));
+        let cents = match cents.len() {
+            0 => 0,
+            1 => try!(cents.parse::<usize>().map_err(|_| invalid())) * 10,
+            _ => try!(cents.parse::<usize>().map_err(|_| invalid())),
+        };
+
+        dollars.checked_mul(100)
+            .and_then(|total| total.checked_add(cents))
+            .map(Price)
+            .ok_or_else(invalid)
+    }
+}
+
+impl From<usize> for Price {
+    fn from(cents: usize) -> Price {
+        Price(cents)
+    }
+}
+
+impl From<Price> for usize {
+    fn from(price: Price) -> usize {
+        price.0
+    }
+}
+
+impl Serialize for Price {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+impl<'de> Deserialize<'de> for Price {
+    fn deserialize<D>(deserializer: D) -> result::Result<Price, D::Error> where D: Deserializer<'de> {
+        usize::deserialize(deserializer).map(Price)
+    }
+}
@@ -0,0 +1,154 @@
+//! Client-side token bucket rate limiting
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use {Result, StockfighterError};
+
+/// What a call does when its budget is used up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitMode {
+    /// Wait until a token is available
+    Block,
+    /// Return `StockfighterError::RateLimited` right away
+    Fail,
+}
+
+/// The budget a request draws from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    /// Quotes, order books, heartbeats and order status
+    Read,
+    /// Placing and cancelling orders
+    OrderEntry,
+}
+
+/// Limits how fast requests are sent, with separate budgets for reads and for order entry.
+///
+/// Share one limiter between several clients by passing the same `Arc` to each builder.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use stockfighter::{StockfighterBuilder, RateLimiter, RateLimitMode};
+///
+/// let limiter = RateLimiter::new(RateLimitMode::Block)
+///     .reads(20, Duration::from_secs(1))
+///     .orders(5, Duration::from_secs(1));
+///
+/// let sf = StockfighterBuilder::new("fake api key").rate_limiter(Arc::new(limiter)).build();
+/// assert!(sf.is_ok());
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    mode: RateLimitMode,
+    reads: Option<TokenBucket>,
+    orders: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+
+    /// A limiter with unlimited budgets until `reads` or `orders` is set
+    pub fn new(mode: RateLimitMode) -> RateLimiter {
+        RateLimiter { mode: mode, reads: None, orders: None }
+    }
+
+    /// Allow bursts of `requests` reads, refilled at `requests` per `per`
+    pub fn reads(mut self, requests: u32, per: Duration) -> RateLimiter {
+        self.reads = Some(TokenBucket::new(requests, per));
+        self
+    }
+
+    /// Allow bursts of `requests` order entries, refilled at `requests` per `per`
+    pub fn orders(mut self, requests: u32, per: Duration) -> RateLimiter {
+        self.orders = Some(TokenBucket::new(requests, per));
+        self
+    }
+
+    pub fn mode(&self) -> RateLimitMode {
+        self.mode
+    }
+
+    /// Take a token from the `kind` budget, blocking or failing when there is none left
+    pub fn acquire(&self, kind: RequestKind) -> Result<()> {
+        self.acquire_before(kind, None)
+    }
+
+    /// Like `acquire`, but fail with `StockfighterError::Timeout` instead of blocking past
+    /// `deadline`
+    pub fn acquire_before(&self, kind: RequestKind, deadline: Option<Instant>) -> Result<()> {
+        let bucket = match kind {
+            RequestKind::Read => self.reads.as_ref(),
+            RequestKind::OrderEntry => self.orders.as_ref(),
+        };
+
+        let bucket = match bucket {
+            Some(bucket) => bucket,
+            None => return Ok(()),
+        };
+
+        loop {
+            match bucket.try_acquire() {
+                Ok(()) => return Ok(()),
+                Err(_) if self.mode == RateLimitMode::Fail => return Err(StockfighterError::RateLimited),
+                Err(wait) => {
+                    if let Some(deadline) = deadline {
+                        if Instant::now() + wait >= deadline {
+                            return Err(StockfighterError::Timeout);
+                        }
+                    }
+                    trace!("Out of {:?} tokens, waiting {:?}", kind, wait);
+                    thread::sleep(wait);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests: u32, per: Duration) -> TokenBucket {
+        let capacity = requests.max(1) as f64;
+        let per = per.as_secs() as f64 + per.subsec_nanos() as f64 / 1e9;
+
+        TokenBucket {
+            capacity: capacity,
+            tokens_per_sec: if per > 0.0 { capacity / per } else { ::std::f64::INFINITY },
+            state: Mutex::new(BucketState { tokens: capacity, refilled_at: Instant::now() }),
+        }
+    }
+
+    /// Take a token, or say how long until the next one is available
+    fn try_acquire(&self) -> ::std::result::Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        state.tokens = (state.tokens + elapsed * self.tokens_per_sec).min(self.capacity);
+        state.refilled_at = now;
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - state.tokens) / self.tokens_per_sec;
+            Err(Duration::new(wait as u64, (wait.fract() * 1e9) as u32))
+        }
+    }
+}
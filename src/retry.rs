@@ -0,0 +1,132 @@
+//! Retrying of failed requests with exponential backoff
+
+use std::time::Duration;
+
+use rand;
+
+use {StockfighterError, millis};
+
+/// Decides whether, and after how long, a failed request is sent again.
+///
+/// Only read-only requests are retried by default. A retried `new_order` can create a duplicate
+/// order, so it is only retried after opting in with `retry_new_orders(true)`.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use stockfighter::{StockfighterBuilder, RetryPolicy};
+///
+/// let policy = RetryPolicy::new()
+///     .max_attempts(5)
+///     .base_delay(Duration::from_millis(50))
+///     .jitter(0.5);
+///
+/// let sf = StockfighterBuilder::new("fake api key").retry_policy(policy).build();
+/// assert!(sf.is_ok());
+/// ```
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retryable: fn(&StockfighterError) -> bool,
+    retry_new_orders: bool,
+}
+
+impl RetryPolicy {
+
+    /// Three attempts, starting with a 100ms delay that doubles up to 2s, with 50% jitter
+    pub fn new() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            jitter: 0.5,
+            retryable: is_transient,
+            retry_new_orders: false,
+        }
+    }
+
+    /// Never retry
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::new().max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one
+    pub fn max_attempts(mut self, max_attempts: u32) -> RetryPolicy {
+        self.max_attempts = if max_attempts == 0 { 1 } else { max_attempts };
+        self
+    }
+
+    /// Delay before the first retry. Each further retry waits twice as long.
+    pub fn base_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound for the delay between two attempts
+    pub fn max_delay(mut self, delay: Duration) -> RetryPolicy {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Fraction (0.0 to 1.0) of each delay that is randomly taken off, so that many clients
+    /// failing at once do not retry in lockstep
+    pub fn jitter(mut self, jitter: f64) -> RetryPolicy {
+        self.jitter = jitter.max(0.0).min(1.0);
+        self
+    }
+
+    /// Replace `is_transient` as the test for which errors are worth retrying
+    pub fn retry_if(mut self, retryable: fn(&StockfighterError) -> bool) -> RetryPolicy {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Also retry `new_order`. Only do this if a duplicate order is acceptable.
+    pub fn retry_new_orders(mut self, retry: bool) -> RetryPolicy {
+        self.retry_new_orders = retry;
+        self
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn retries_new_orders(&self) -> bool {
+        self.retry_new_orders
+    }
+
+    pub fn is_retryable(&self, err: &StockfighterError) -> bool {
+        (self.retryable)(err)
+    }
+
+    /// How long to wait after failed attempt number `attempt` (starting at 1)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = millis(self.base_delay);
+        let max = millis(self.max_delay);
+
+        let exponent = if attempt > 0 { attempt - 1 } else { 0 };
+        let delay = base.checked_mul(1 << exponent.min(31)).unwrap_or(max).min(max);
+        let jitter = (delay as f64 * self.jitter * rand::random::<f64>()) as u64;
+
+        Duration::from_millis(delay - jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy::new()
+    }
+}
+
+/// Errors that are likely to go away on their own: network failures, timeouts and 5xx responses
+pub fn is_transient(err: &StockfighterError) -> bool {
+    match *err {
+        StockfighterError::Hyper(_) | StockfighterError::Io(_) | StockfighterError::Timeout => true,
+        StockfighterError::Api { status, .. } => status >= 500,
+        _ => false,
+    }
+}
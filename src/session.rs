@@ -0,0 +1,213 @@
+//! A started level, with everything needed to trade in it
+
+use std::time::{Duration, Instant};
+use std::thread;
+
+use gm::{Gamemaster, LevelInstance, InstanceEvent};
+use stream::Subscription;
+use {Result, StockfighterError, Stockfighter, IntoChecked, Level, InstanceId, Account, Venue, Symbol};
+use {Price, Qty, OrderId, OrderDirection, OrderType};
+use {Quote, OrderbookList, OrderStatus, StockOrdersStatuses, ExecutionOrdersStatuses};
+
+/// How long, in seconds, a new level's venues get to come up
+const VENUE_WAIT: u64 = 30;
+
+/// How often, in milliseconds, a venue that is not up yet is checked again
+const VENUE_POLL_INTERVAL: u64 = 500;
+
+/// A running level instance, its client and its executions feed
+///
+/// The session trades as the level's account on its first venue and ticker, so `quote`,
+/// `new_order` and the other order book calls need none of them. Levels with more than one venue
+/// or ticker bind the first of each; the others are in `instance`, and `client` reaches them. The
+/// executions feed for the account on that venue is opened with the session.
+///
+/// Dropping the session stops the instance, unless `keep_running` was set so that it can be
+/// resumed later.
+///
+/// # Example
+///
+/// ```no_run
+/// use stockfighter::{Stockfighter, LevelSession, OrderDirection, OrderType, StreamEvent};
+///
+/// let sf = Stockfighter::new("fake api key");
+/// let session = LevelSession::start(&sf, "first_steps").unwrap();
+///
+/// println!("{:?}", session.quote().unwrap());
+/// session.new_order(5000, 100, OrderDirection::Buy, OrderType::Market).unwrap();
+///
+/// while let Some(event) = session.executions().recv() {
+///     if let StreamEvent::Message(execution) = event {
+///         println!("{:?}", execution);
+///     }
+/// }
+/// ```
+pub struct LevelSession {
+    gm: Gamemaster,
+    instance: LevelInstance,
+    client: Stockfighter,
+    account: Account,
+    venue: Venue,
+    symbol: Option<Symbol>,
+    executions: Subscription<ExecutionOrdersStatuses>,
+    keep_running: bool,
+}
+
+impl LevelSession {
+
+    /// Start `level` and wait for its venues to come up. The instance is stopped again if they do
+    /// not, or if the executions feed cannot be opened.
+    pub fn start<L>(sf: &Stockfighter, level: L) -> Result<LevelSession> where L: IntoChecked<Level> {
+        let gm = Gamemaster::new(sf);
+        let instance = try!(gm.start_level(level));
+        let id = instance.instance_id;
+
+        LevelSession::open(sf, gm.clone(), instance).map_err(|err| {
+            if let Err(stop_err) = gm.stop(id) {
+                warn!("Could not stop instance {} after failing to open it: {}", id, stop_err);
+            }
+            err
+        })
+    }
+
+    /// Resume an instance that is still running and wait for its venues to come up
+    pub fn resume<I>(sf: &Stockfighter, instance: I) -> Result<LevelSession> where I: IntoChecked<InstanceId> {
+        let gm = Gamemaster::new(sf);
+        let instance = try!(gm.resume(instance));
+        LevelSession::open(sf, gm, instance)
+    }
+
+    fn open(sf: &Stockfighter, gm: Gamemaster, instance: LevelInstance) -> Result<LevelSession> {
+        let account = try!(Account::new(&instance.account[..]));
+        let venue = match instance.venues.first() {
+            Some(venue) => try!(Venue::new(&venue[..])),
+            None => return Err(StockfighterError::InvalidArgument("the level has no venues".to_owned())),
+        };
+        let symbol = match instance.tickers.first() {
+            Some(ticker) => Some(try!(Symbol::new(&ticker[..]))),
+            None => None,
+        };
+
+        for venue in &instance.venues {
+            try!(wait_for_venue(sf, &venue[..]));
+        }
+
+        let client = sf.with_defaults(account.clone(), venue.clone(), symbol.clone());
+        let executions = try!(client.executions_venue(&account, &venue));
+
+        Ok(LevelSession {
+            gm: gm,
+            instance: instance,
+            client: client,
+            account: account,
+            venue: venue,
+            symbol: symbol,
+            executions: executions,
+            keep_running: false,
+        })
+    }
+
+    /// What the gamemaster said about the level when it was started or resumed
+    pub fn instance(&self) -> &LevelInstance {
+        &self.instance
+    }
+
+    /// Client whose default account, venue and symbol are the level's, for calls the session
+    /// does not make itself
+    pub fn client(&self) -> &Stockfighter {
+        &self.client
+    }
+
+    /// Quote for the level's ticker
+    pub fn quote(&self) -> Result<Quote> {
+        self.client.quote(&self.venue, try!(self.symbol()))
+    }
+
+    /// Order book for the level's ticker
+    pub fn orderbook(&self) -> Result<OrderbookList> {
+        self.client.orderbook_for_stock(&self.venue, try!(self.symbol()))
+    }
+
+    /// Place an order for the level's ticker as the level's account
+    ///
+    /// As with `Stockfighter::new_order`, a numeric `price` is in cents and a string one in
+    /// dollars, and an order that fails with `StockfighterError::Timeout` may still have been
+    /// placed.
+    pub fn new_order<P, Q>(&self, price: P, qty: Q, direction: OrderDirection, order_type: OrderType)
+                           -> Result<OrderStatus>
+        where P: IntoChecked<Price>, Q: IntoChecked<Qty> {
+
+        self.client.new_order(&self.account, &self.venue, try!(self.symbol()), price, qty, direction, order_type)
+    }
+
+    pub fn order_status<I>(&self, id: I) -> Result<OrderStatus> where I: IntoChecked<OrderId> {
+        self.client.existing_order_status(id, &self.venue, try!(self.symbol()))
+    }
+
+    pub fn cancel_order<I>(&self, id: I) -> Result<OrderStatus> where I: IntoChecked<OrderId> {
+        self.client.cancel_an_order(&self.venue, try!(self.symbol()), id)
+    }
+
+    /// Status of every order the level's account has placed on its venue
+    pub fn orders(&self) -> Result<StockOrdersStatuses> {
+        self.client.status_for_all_orders(&self.venue, &self.account)
+    }
+
+    fn symbol(&self) -> Result<&Symbol> {
+        match self.symbol {
+            Some(ref symbol) => Ok(symbol),
+            None => Err(StockfighterError::InvalidArgument("the level has no ticker".to_owned())),
+        }
+    }
+
+    pub fn gamemaster(&self) -> &Gamemaster {
+        &self.gm
+    }
+
+    /// The executions feed for the level's account and venue
+    pub fn executions(&self) -> &Subscription<ExecutionOrdersStatuses> {
+        &self.executions
+    }
+
+    /// Watch the instance for trading days, flash messages and its end, see
+    /// `Gamemaster::watch_instance`
+    pub fn watch(&self, interval: Duration) -> Result<Subscription<InstanceEvent>> {
+        self.gm.watch_instance(self.instance.instance_id, interval)
+    }
+
+    /// Whether to leave the instance running when the session is dropped
+    pub fn keep_running(&mut self, keep: bool) {
+        self.keep_running = keep;
+    }
+
+    /// Stop the instance now, reporting any error that dropping the session would only log
+    pub fn stop(mut self) -> Result<()> {
+        self.keep_running = true;
+        self.gm.stop(self.instance.instance_id)
+    }
+}
+
+impl Drop for LevelSession {
+    fn drop(&mut self) {
+        if self.keep_running {
+            return;
+        }
+        if let Err(err) = self.gm.stop(self.instance.instance_id) {
+            warn!("Could not stop instance {}: {}", self.instance.instance_id, err);
+        }
+    }
+}
+
+/// Check `venue` until it is up, giving up after `VENUE_WAIT` seconds
+fn wait_for_venue(sf: &Stockfighter, venue: &str) -> Result<()> {
+    let until = Instant::now() + Duration::from_secs(VENUE_WAIT);
+    loop {
+        match sf.venue_heartbeat(venue) {
+            Err(StockfighterError::VenueDown(_)) if Instant::now() < until => {
+                debug!("Venue {} is not up yet", venue);
+                thread::sleep(Duration::from_millis(VENUE_POLL_INTERVAL));
+            }
+            result => return result,
+        }
+    }
+}
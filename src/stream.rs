@@ -0,0 +1,561 @@
+//! Ticker tape and executions feeds that survive dropped connections
+//!
+//! Each feed runs on its own thread. When the socket drops, the thread reconnects to the same URL
+//! with the backoff of the client's reconnect policy, and tells the subscriber about the gap with
+//! `StreamEvent::Disconnected` and `StreamEvent::Reconnected`. The thread runs until its
+//! `SubscriptionHandle` is stopped or dropped.
+//!
+//! A feed also pings the server now and then. The round trip of the answer, and when the last
+//! message arrived, can be read from the handle. A connection that has been silent for too long,
+//! pongs included, is reported with `StreamEvent::Stale`.
+
+use std::cmp;
+use std::result;
+use std::str;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver as EventReceiver, TryRecvError, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json;
+use websocket::{Message, Sender};
+use websocket::message::Type;
+
+use hub::Registration;
+use ws::{self, Connection};
+use {Result, StockfighterError, RetryPolicy, TickerTape, TickerTapeQuote, ExecutionOrdersStatuses};
+
+/// How often, in milliseconds, a feed thread checks whether it has been stopped
+const STOP_POLL_INTERVAL: u64 = 100;
+
+/// How often, in seconds, a feed pings the server by default
+pub const DEFAULT_PING_INTERVAL: u64 = 10;
+
+/// After how many seconds of silence a feed is stale by default
+pub const DEFAULT_STALE_AFTER: u64 = 30;
+
+/// What a feed subscriber receives
+#[derive(Debug)]
+pub enum StreamEvent<T> {
+    /// A message from the feed
+    Message(T),
+    /// The connection dropped. Messages sent by the server until `Reconnected` are lost.
+    Disconnected,
+    /// The connection is back after being down for `downtime`. Anything that happened in the
+    /// meantime was missed, so this is the time to fetch a fresh `orderbook_for_stock`.
+    Reconnected { downtime: Duration },
+    /// Nothing, not even a pong, has arrived since `since`. The connection is kept, and the next
+    /// `Message` shows the feed is live again; a subscriber that cannot trade on old data may
+    /// want to stop instead.
+    Stale { since: Instant },
+    /// Something went wrong without ending the feed: a message that could not be decoded, or the
+    /// reason for a `Disconnected` that follows. When reconnecting is given up, the last error is
+    /// sent before the feed ends.
+    Error(StockfighterError),
+}
+
+/// Controls the thread of a running feed
+///
+/// `stop` sends the server a close frame, ends the thread and waits for it. Dropping the handle
+/// does the same, so a feed never outlives its handle.
+///
+/// The feed's callback, or the state passed to one of the `*_with_state` calls, is handed back by
+/// `stop` and `join`. Only the feed's thread ever touches it, so it needs no locking.
+///
+/// # Example
+///
+/// ```no_run
+/// use stockfighter::{Stockfighter, StreamEvent};
+///
+/// let sf = Stockfighter::new("fake api key");
+/// let handle = sf.executions_venue_with_state("EXB123456", "TESTEX", 0, |filled, event| {
+///     if let StreamEvent::Message(execution) = event {
+///         *filled += execution.filled;
+///     }
+/// }).unwrap();
+///
+/// // ... later
+/// let filled = handle.stop().unwrap();
+/// println!("{} shares filled", filled);
+/// ```
+pub struct SubscriptionHandle<S> {
+    stop: Arc<AtomicBool>,
+    // Set by the thread as it exits, whether it gave up, was stopped or panicked
+    finished: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<S>>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl<S> SubscriptionHandle<S> {
+
+    /// Close the feed and wait for its thread to finish. Returns the feed's state, or `None` if
+    /// the callback panicked.
+    pub fn stop(mut self) -> Option<S> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.join_thread()
+    }
+
+    /// Wait for the feed to end on its own, which it only does once it gives up reconnecting
+    pub fn join(mut self) -> Option<S> {
+        self.join_thread()
+    }
+
+    /// Whether the feed is still connected or reconnecting
+    pub fn is_running(&self) -> bool {
+        !self.stop.load(Ordering::SeqCst) && !self.finished.load(Ordering::SeqCst) && self.thread.is_some()
+    }
+
+    /// When the last message arrived, `None` until the first one does
+    pub fn last_message_at(&self) -> Option<Instant> {
+        self.stats.lock().unwrap().last_message
+    }
+
+    /// Round trip of the latest answered ping, `None` until one is answered
+    pub fn ping_rtt(&self) -> Option<Duration> {
+        self.stats.lock().unwrap().ping_rtt
+    }
+
+    fn join_thread(&mut self) -> Option<S> {
+        match self.thread.take() {
+            // A callback stopping its own feed cannot wait for itself
+            Some(ref thread) if thread.thread().id() == thread::current().id() => None,
+            Some(thread) => thread.join().ok(),
+            None => None,
+        }
+    }
+}
+
+impl<S> Drop for SubscriptionHandle<S> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.join_thread();
+    }
+}
+
+/// A running feed whose events are read from a channel
+///
+/// A `Subscription` is an `Iterator` over its events that ends when the feed gives up
+/// reconnecting. `try_recv` and `recv_timeout` allow one thread to poll several feeds. Dropping
+/// the subscription closes the feed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use stockfighter::{Stockfighter, StreamEvent};
+///
+/// let sf = Stockfighter::new("fake api key");
+/// let quotes = sf.ticker_tape_venue("EXB123456", "TESTEX").unwrap();
+/// let executions = sf.executions_venue("EXB123456", "TESTEX").unwrap();
+///
+/// loop {
+///     if let Ok(StreamEvent::Message(quote)) = quotes.recv_timeout(Duration::from_millis(10)) {
+///         println!("{:?}", quote);
+///     }
+///     while let Ok(StreamEvent::Message(execution)) = executions.try_recv() {
+///         println!("{:?}", execution);
+///     }
+/// }
+/// ```
+pub struct Subscription<T> {
+    rx: EventReceiver<StreamEvent<T>>,
+    source: Source<T>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+// What produces a subscription's events: a thread of its own, or a `MarketDataHub`
+enum Source<T> {
+    Thread(SubscriptionHandle<()>),
+    Hub(Registration),
+}
+
+impl<T> Subscription<T> {
+
+    /// The channel the events arrive on
+    pub fn receiver(&self) -> &EventReceiver<StreamEvent<T>> {
+        &self.rx
+    }
+
+    /// Wait for the next event. `None` once the feed has ended.
+    pub fn recv(&self) -> Option<StreamEvent<T>> {
+        self.rx.recv().ok()
+    }
+
+    /// The next event, if one is waiting
+    pub fn try_recv(&self) -> result::Result<StreamEvent<T>, TryRecvError> {
+        self.rx.try_recv()
+    }
+
+    /// Wait at most `timeout` for the next event
+    pub fn recv_timeout(&self, timeout: Duration) -> result::Result<StreamEvent<T>, RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// When the last message arrived, see `SubscriptionHandle::last_message_at`
+    pub fn last_message_at(&self) -> Option<Instant> {
+        self.stats.lock().unwrap().last_message
+    }
+
+    /// Round trip of the latest answered ping, see `SubscriptionHandle::ping_rtt`
+    pub fn ping_rtt(&self) -> Option<Duration> {
+        self.stats.lock().unwrap().ping_rtt
+    }
+
+    /// Close the feed, see `SubscriptionHandle::stop`. A subscription from a `MarketDataHub` is
+    /// only removed from the hub, whose socket stays open for other subscribers.
+    pub fn stop(self) {
+        match self.source {
+            Source::Thread(handle) => { let _ = handle.stop(); }
+            Source::Hub(registration) => drop(registration),
+        }
+    }
+}
+
+impl<T> Iterator for Subscription<T> {
+    type Item = StreamEvent<T>;
+
+    fn next(&mut self) -> Option<StreamEvent<T>> {
+        self.recv()
+    }
+}
+
+/// A subscription fed by a `MarketDataHub`, which stops feeding it once `registration` is dropped
+pub fn hub_subscription<T>(rx: EventReceiver<StreamEvent<T>>, registration: Registration, stats: Arc<Mutex<Stats>>)
+                           -> Subscription<T> {
+    Subscription { rx: rx, source: Source::Hub(registration), stats: stats }
+}
+
+/// How a feed connects, reconnects and watches over a quiet connection
+#[derive(Clone)]
+pub struct FeedConfig {
+    pub connect_timeout: Option<Duration>,
+    pub reconnect: RetryPolicy,
+    pub ping_interval: Option<Duration>,
+    pub stale_after: Option<Duration>,
+}
+
+/// What a feed has seen lately, shared by whoever reads it and its handle. Kept across
+/// reconnects.
+#[derive(Default)]
+pub struct Stats {
+    pub last_message: Option<Instant>,
+    pub ping_rtt: Option<Duration>,
+}
+
+/// Pings a connection and notices when it goes quiet
+pub struct Keepalive {
+    ping_interval: Option<Duration>,
+    stale_after: Option<Duration>,
+    next_ping: Option<Instant>,
+    // Sequence number and send time of the last ping
+    ping: Option<(u64, Instant)>,
+    sequence: u64,
+    last_frame: Instant,
+    stale: bool,
+}
+
+impl Keepalive {
+
+    /// Watch a connection that has just been made
+    pub fn new(config: &FeedConfig) -> Keepalive {
+        let now = Instant::now();
+        Keepalive {
+            ping_interval: config.ping_interval,
+            stale_after: config.stale_after,
+            next_ping: config.ping_interval.map(|interval| now + interval),
+            ping: None,
+            sequence: 0,
+            last_frame: now,
+            stale: false,
+        }
+    }
+
+    /// The ping to send, if one is due
+    pub fn ping(&mut self, now: Instant) -> Option<Message<'static>> {
+        match (self.next_ping, self.ping_interval) {
+            (Some(next), Some(interval)) if now >= next => {
+                self.sequence += 1;
+                self.ping = Some((self.sequence, now));
+                self.next_ping = Some(now + interval);
+                Some(Message::ping(self.sequence.to_string().into_bytes()))
+            }
+            _ => None,
+        }
+    }
+
+    /// When the connection went quiet, the first time it has been quiet for too long
+    pub fn stale(&mut self, now: Instant) -> Option<Instant> {
+        match self.stale_after {
+            Some(after) if !self.stale && now >= self.last_frame + after => {
+                self.stale = true;
+                Some(self.last_frame)
+            }
+            _ => None,
+        }
+    }
+
+    /// Note a frame from the server. Returns the round trip if it answers the last ping.
+    pub fn received(&mut self, message: &Message) -> Option<Duration> {
+        self.last_frame = Instant::now();
+        self.stale = false;
+
+        match self.ping {
+            Some((sequence, sent)) if message.opcode == Type::Pong
+                && &message.payload[..] == sequence.to_string().as_bytes() => {
+                self.ping = None;
+                Some(self.last_frame - sent)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reconnect forever, waiting 250ms after the first failure and backing off to 10s
+pub fn default_reconnect_policy() -> RetryPolicy {
+    RetryPolicy::new()
+        .max_attempts(u32::max_value())
+        .base_delay(Duration::from_millis(250))
+        .max_delay(Duration::from_secs(10))
+}
+
+pub fn decode_quote(text: &str) -> serde_json::Result<TickerTapeQuote> {
+    serde_json::from_str::<TickerTape>(text).map(|tape| tape.quote)
+}
+
+pub fn decode_execution(text: &str) -> serde_json::Result<ExecutionOrdersStatuses> {
+    serde_json::from_str(text)
+}
+
+/// Connect to `url` and hand every event, together with `state`, to `f` on a new thread. Only the
+/// first connection attempt is made before returning, so a bad URL or key is reported to the
+/// caller. The thread hands `state` back when it ends.
+pub fn spawn<T, S, F>(url: String, config: FeedConfig, decode: fn(&str) -> serde_json::Result<T>,
+                      mut state: S, mut f: F) -> Result<SubscriptionHandle<S>>
+    where T: 'static, S: Send + 'static, F: FnMut(&mut S, StreamEvent<T>) + Send + 'static {
+
+    let connection = try!(ws::connect(&url, config.connect_timeout));
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let shared_stats = stats.clone();
+
+    Ok(spawn_thread(stats, move |stop| {
+        trace!("Spawned thread for websocket {}", url);
+        let feed = Feed { url: &url, config: &config, decode: decode, stop: stop, stats: &shared_stats };
+        feed.run(connection, &mut |event| f(&mut state, event));
+        state
+    }))
+}
+
+/// Run `f` on a new thread behind a handle. `f` is given the flag the handle sets when stopped,
+/// and returns the state the handle hands back.
+pub fn spawn_thread<S, F>(stats: Arc<Mutex<Stats>>, f: F) -> SubscriptionHandle<S>
+    where S: Send + 'static, F: FnOnce(&AtomicBool) -> S + Send + 'static {
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let finished = Arc::new(AtomicBool::new(false));
+    let (stopped, exited) = (stop.clone(), finished.clone());
+    let thread = thread::spawn(move || {
+        let _finished = Finished(exited);
+        f(&stopped)
+    });
+
+    SubscriptionHandle { stop: stop, finished: finished, thread: Some(thread), stats: stats }
+}
+
+/// Sets its flag when dropped, which a feed thread does as it exits, even by panicking
+struct Finished(Arc<AtomicBool>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Run a feed that sends its events to a `Subscription`
+pub fn subscription<T>(url: String, config: FeedConfig, decode: fn(&str) -> serde_json::Result<T>)
+                       -> Result<Subscription<T>>
+    where T: Send + 'static {
+
+    // The callback owns the sender, so it is dropped and the subscription ends with the thread.
+    // Handing it back as the thread's state would keep it alive in the handle.
+    let (tx, rx) = mpsc::channel();
+    let handle = try!(spawn(url, config, decode, (), move |_, event| {
+        // The subscriber may have gone away, which is fine
+        let _ = tx.send(event);
+    }));
+
+    Ok(thread_subscription(rx, handle))
+}
+
+/// A subscription fed by the thread behind `handle`. The thread must drop its sender when it
+/// ends, which ends the subscription.
+pub fn thread_subscription<T>(rx: EventReceiver<StreamEvent<T>>, handle: SubscriptionHandle<()>) -> Subscription<T> {
+    let stats = handle.stats.clone();
+    Subscription { rx: rx, source: Source::Thread(handle), stats: stats }
+}
+
+// Everything a feed thread needs besides its connection and callback
+struct Feed<'a, T> {
+    url: &'a str,
+    config: &'a FeedConfig,
+    decode: fn(&str) -> serde_json::Result<T>,
+    stop: &'a AtomicBool,
+    stats: &'a Mutex<Stats>,
+}
+
+enum Ended {
+    Stopped,
+    Lost,
+}
+
+impl<'a, T> Feed<'a, T> {
+
+    /// Read from `connection`, and reconnect whenever it drops, until the feed is stopped or gives
+    /// up
+    fn run<F>(&self, mut connection: Connection, f: &mut F) where F: FnMut(StreamEvent<T>) {
+        loop {
+            if let Ended::Stopped = self.read(connection, f) {
+                return;
+            }
+            f(StreamEvent::Disconnected);
+
+            match reconnect_to(self.url, self.config.connect_timeout, &self.config.reconnect, self.stop) {
+                Ok(Some((reconnected, downtime))) => {
+                    connection = reconnected;
+                    f(StreamEvent::Reconnected { downtime: downtime });
+                }
+                Ok(None) => return,
+                Err(err) => {
+                    f(StreamEvent::Error(err));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Read messages until the connection drops, the server closes it or the feed is stopped
+    fn read<F>(&self, connection: Connection, f: &mut F) -> Ended where F: FnMut(StreamEvent<T>) {
+        let url = self.url;
+        let (mut sender, mut receiver) = connection.split();
+        let mut keepalive = Keepalive::new(self.config);
+
+        loop {
+            if self.stop.load(Ordering::SeqCst) {
+                debug!("Closing websocket {}", url);
+                let _ = sender.send_message(&Message::close());
+                return Ended::Stopped;
+            }
+
+            let now = Instant::now();
+            if let Some(ping) = keepalive.ping(now) {
+                if let Err(err) = sender.send_message(&ping) {
+                    debug!("Lost websocket {}: {}", url, err);
+                    f(StreamEvent::Error(err.into()));
+                    return Ended::Lost;
+                }
+            }
+            if let Some(since) = keepalive.stale(now) {
+                warn!("Websocket {} has been silent for {:?}", url, now - since);
+                f(StreamEvent::Stale { since: since });
+            }
+
+            let message = match ws::poll(&mut receiver, Duration::from_millis(STOP_POLL_INTERVAL)) {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(err) => {
+                    debug!("Lost websocket {}: {}", url, err);
+                    f(StreamEvent::Error(err));
+                    return Ended::Lost;
+                }
+            };
+            trace!("Received message {:?} from websocket {}", message, url);
+
+            if let Some(rtt) = keepalive.received(&message) {
+                self.stats.lock().unwrap().ping_rtt = Some(rtt);
+            }
+
+            match message.opcode {
+                Type::Text => {
+                    self.stats.lock().unwrap().last_message = Some(Instant::now());
+                    f(match decode_text(&message.payload, self.decode) {
+                        Ok(decoded) => StreamEvent::Message(decoded),
+                        Err(err) => {
+                            warn!("Skipping message from websocket {}: {}", url, err);
+                            StreamEvent::Error(err)
+                        }
+                    })
+                }
+                Type::Close => {
+                    debug!("Websocket {} closed by the server", url);
+                    let _ = sender.send_message(&Message::close());
+                    return Ended::Lost;
+                }
+                Type::Ping => {
+                    if let Err(err) = sender.send_message(&Message::pong(message.payload)) {
+                        f(StreamEvent::Error(err.into()));
+                        return Ended::Lost;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Decode a text frame, keeping the payload in the error if that fails
+pub fn decode_text<T>(payload: &[u8], decode: fn(&str) -> serde_json::Result<T>) -> Result<T> {
+    let text = match str::from_utf8(payload) {
+        Ok(text) => text,
+        Err(err) => return Err(StockfighterError::Decode {
+            payload: String::from_utf8_lossy(payload).into_owned(),
+            cause: Box::new(err),
+        }),
+    };
+
+    decode(text).map_err(|err| StockfighterError::Decode { payload: text.to_owned(), cause: Box::new(err) })
+}
+
+/// Connect again, backing off between attempts. Returns the connection and how long the feed was
+/// down, `None` if the feed was stopped, or the last error once the policy's attempts are used up.
+fn reconnect_to(url: &str, connect_timeout: Option<Duration>, policy: &RetryPolicy, stop: &AtomicBool)
+                -> Result<Option<(Connection, Duration)>> {
+
+    let down_since = Instant::now();
+
+    let mut attempt = 1;
+    loop {
+        if !sleep_unless_stopped(policy.delay(attempt), stop) {
+            return Ok(None);
+        }
+
+        match ws::connect(url, connect_timeout) {
+            Ok(connection) => {
+                info!("Reconnected websocket {} after {} attempts", url, attempt);
+                return Ok(Some((connection, down_since.elapsed())));
+            }
+            Err(err) if attempt < policy.attempts() => {
+                debug!("Reconnecting websocket {} failed ({}), attempt {}", url, err, attempt);
+                attempt += 1;
+            }
+            Err(err) => {
+                warn!("Giving up on websocket {} after {} attempts", url, attempt);
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Sleep for `duration`, waking up early if the feed is stopped. Returns false if it was.
+pub fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) -> bool {
+    let until = Instant::now() + duration;
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        let now = Instant::now();
+        if now >= until {
+            return true;
+        }
+        thread::sleep(cmp::min(until - now, Duration::from_millis(STOP_POLL_INTERVAL)));
+    }
+}
@@ -0,0 +1,117 @@
+//! Exchange timestamps
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Deref;
+use std::result;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::Error as DeError;
+
+use {Result, StockfighterError};
+
+/// A UTC timestamp sent by the exchange, e.g. `2015-07-13T05:38:17.33640392Z`
+///
+/// The full fractional seconds are kept, and the string the exchange sent is available from `raw`.
+/// A `Timestamp` dereferences to a `chrono::DateTime<Utc>`.
+///
+/// # Example
+///
+/// ```rust
+/// use stockfighter::Timestamp;
+///
+/// let ts: Timestamp = "2015-07-13T05:38:17.33640392Z".parse().unwrap();
+/// assert_eq!(ts.raw(), "2015-07-13T05:38:17.33640392Z");
+/// assert_eq!(ts.timestamp_subsec_nanos(), 336403920);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Timestamp {
+    time: DateTime<Utc>,
+    raw: String,
+}
+
+impl Timestamp {
+
+    pub fn parse(raw: &str) -> Result<Timestamp> {
+        match DateTime::parse_from_rfc3339(raw) {
+            Ok(time) => Ok(Timestamp { time: time.with_timezone(&Utc), raw: raw.to_owned() }),
+            Err(_) => Err(StockfighterError::InvalidTimestamp(raw.to_owned())),
+        }
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    /// The timestamp exactly as the exchange sent it
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// Time elapsed since this timestamp, by the local clock
+    pub fn age(&self) -> Duration {
+        Utc::now().signed_duration_since(self.time)
+    }
+
+    /// Time elapsed from `earlier` to this timestamp
+    pub fn since(&self, earlier: &Timestamp) -> Duration {
+        self.time.signed_duration_since(earlier.time)
+    }
+}
+
+impl Deref for Timestamp {
+    type Target = DateTime<Utc>;
+
+    fn deref(&self) -> &DateTime<Utc> {
+        &self.time
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Timestamp) -> bool {
+        self.time == other.time
+    }
+}
+
+impl Eq for Timestamp {}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Timestamp) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Timestamp) -> Ordering {
+        self.time.cmp(&other.time)
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = StockfighterError;
+
+    fn from_str(s: &str) -> Result<Timestamp> {
+        Timestamp::parse(s)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error> where S: Serializer {
+        serializer.serialize_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D>(deserializer: D) -> result::Result<Timestamp, D::Error> where D: Deserializer<'de> {
+        let raw = try!(String::deserialize(deserializer));
+        Timestamp::parse(&raw).map_err(|_| D::Error::custom(format!("invalid timestamp: {}", raw)))
+    }
+}
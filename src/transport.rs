@@ -0,0 +1,324 @@
+//! HTTP transports that `Stockfighter` sends its REST requests through
+//!
+//! `HyperTransport` talks to the network and is what `Stockfighter::new` uses.
+//! `ScriptedTransport` records every request and answers with canned responses, so code built on
+//! the SDK can be tested without hitting the live API.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper;
+use hyper::Client;
+use hyper::client::pool::{self, Pool, PooledStream};
+use hyper::header::Headers;
+use hyper::method::Method as HyperMethod;
+use hyper::net::{NetworkConnector, HttpStream, HttpsStream, Ssl, Openssl};
+
+use ws::{is_timeout, connect_tcp};
+use {Result, StockfighterError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Get => write!(f, "GET"),
+            Method::Post => write!(f, "POST"),
+            Method::Delete => write!(f, "DELETE"),
+        }
+    }
+}
+
+/// Header carrying the API key. Its value is never shown by `Debug` or in logs.
+pub const AUTH_HEADER: &'static str = "X-Starfighter-Authorization";
+
+const REDACTED: &'static str = "<redacted>";
+
+/// A REST request. Its `Debug` output hides the API key.
+#[derive(Clone, PartialEq)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    /// Shared between requests, as the same headers are sent every time
+    pub headers: Arc<Vec<(String, String)>>,
+    pub body: Option<String>,
+    /// Time left before the call's deadline, if it has one
+    pub timeout: Option<Duration>,
+}
+
+impl Request {
+    /// Value of the first header called `name`, compared case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    /// The headers with the value of the auth header replaced, for logging
+    pub fn redacted_headers(&self) -> Vec<(&str, &str)> {
+        self.headers.iter()
+            .map(|&(ref n, ref v)| if n.eq_ignore_ascii_case(AUTH_HEADER) { (&n[..], REDACTED) } else { (&n[..], &v[..]) })
+            .collect()
+    }
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &self.redacted_headers())
+            .field("body", &self.body)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new<S>(status: u16, body: S) -> Response where S: Into<String> {
+        Response { status: status, body: body.into() }
+    }
+}
+
+pub trait Transport: Send + Sync {
+    /// Send `request` and return the status and body of the response. Only failures to talk to
+    /// the server are errors; a non-200 status is returned as a normal `Response`. A request that
+    /// outlives its `timeout` fails with `StockfighterError::Timeout`.
+    ///
+    /// A request that timed out may still have reached the server, so a new order that failed
+    /// with `Timeout` may have been placed.
+    fn send(&self, request: &Request) -> Result<Response>;
+}
+
+impl<T> Transport for Arc<T> where T: Transport + ?Sized {
+    fn send(&self, request: &Request) -> Result<Response> {
+        (**self).send(request)
+    }
+}
+
+/// How long `HyperTransport::new` waits for a connection to be set up
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+
+/// How long `HyperTransport::new` waits on a socket read or write
+pub const DEFAULT_READ_TIMEOUT: u64 = 30;
+
+/// How many idle keep-alive connections `HyperTransport::new` keeps for reuse
+pub const DEFAULT_MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// Sends requests over the network with a `hyper::Client`
+///
+/// Connections are kept alive and reused from a pool shared by every thread using the transport.
+/// The timeouts are set on the sockets, so a request that times out is abandoned on the calling
+/// thread rather than left running. `connect_timeout` bounds the TCP connect and TLS handshake,
+/// but not the DNS lookup before them, and `read_timeout` bounds each socket read and write.
+///
+/// hyper sets socket timeouts per client, so a request with a deadline goes through a client of
+/// its own whose read and write timeouts are at most the time left. That client draws from the
+/// same pool, so the connection is still reused; only a new connection's connect is bounded by
+/// `connect_timeout` rather than by the deadline.
+pub struct HyperTransport {
+    client: Arc<Client>,
+    // None for a client configured by the caller, whose settings are not ours to change
+    pool: Option<SharedPool>,
+    read_timeout: Option<Duration>,
+}
+
+impl HyperTransport {
+    /// Transport with the default connect and read timeouts
+    pub fn new() -> HyperTransport {
+        HyperTransport::with_timeouts(Some(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT)),
+                                      Some(Duration::from_secs(DEFAULT_READ_TIMEOUT)))
+    }
+
+    /// Transport with the given timeouts. `None` waits forever.
+    pub fn with_timeouts(connect_timeout: Option<Duration>, read_timeout: Option<Duration>) -> HyperTransport {
+        HyperTransport::with_pool(DEFAULT_MAX_IDLE_CONNECTIONS, connect_timeout, read_timeout)
+    }
+
+    /// Transport keeping up to `max_idle` idle connections open, with the given timeouts
+    pub fn with_pool(max_idle: usize, connect_timeout: Option<Duration>, read_timeout: Option<Duration>) -> HyperTransport {
+        let connector = TimeoutConnector { connect_timeout: connect_timeout, ssl: Openssl::default() };
+        let pool = SharedPool(Arc::new(Pool::with_connector(pool::Config { max_idle: max_idle }, connector)));
+        let mut client = Client::with_connector(pool.clone());
+        client.set_read_timeout(read_timeout);
+        client.set_write_timeout(read_timeout);
+
+        HyperTransport { client: Arc::new(client), pool: Some(pool), read_timeout: read_timeout }
+    }
+
+    /// Transport around a client configured by the caller. No timeouts are added, and a request's
+    /// deadline is not enforced.
+    pub fn with_client(client: Client) -> HyperTransport {
+        HyperTransport { client: Arc::new(client), pool: None, read_timeout: None }
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> HyperTransport {
+        HyperTransport::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let (deadline, pool) = match (request.timeout, self.pool.as_ref()) {
+            (Some(deadline), Some(pool)) => (deadline, pool),
+            _ => return send_with(&self.client, request),
+        };
+
+        let mut client = Client::with_connector(pool.clone());
+        client.set_read_timeout(Some(shortest(self.read_timeout, deadline)));
+        client.set_write_timeout(Some(shortest(self.read_timeout, deadline)));
+        send_with(&client, request)
+    }
+}
+
+fn shortest(timeout: Option<Duration>, deadline: Duration) -> Duration {
+    timeout.map_or(deadline, |timeout| cmp::min(timeout, deadline))
+}
+
+/// The transport's connection pool, shared by its own client and those made for deadlines
+#[derive(Clone)]
+struct SharedPool(Arc<Pool<TimeoutConnector>>);
+
+impl NetworkConnector for SharedPool {
+    type Stream = PooledStream<HttpsStream<<Openssl as Ssl>::Stream>>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Self::Stream> {
+        self.0.connect(host, port, scheme)
+    }
+}
+
+/// Connects like hyper's `HttpsConnector`, but gives up on the TCP connect and TLS handshake
+/// after `connect_timeout`
+struct TimeoutConnector {
+    connect_timeout: Option<Duration>,
+    ssl: Openssl,
+}
+
+impl NetworkConnector for TimeoutConnector {
+    type Stream = HttpsStream<<Openssl as Ssl>::Stream>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Self::Stream> {
+        let tcp = try!(connect_tcp(host, port, self.connect_timeout));
+        match scheme {
+            "http" => Ok(HttpsStream::Http(HttpStream(tcp))),
+            "https" => {
+                // The client sets its own read and write timeouts once the stream is handed over
+                try!(tcp.set_read_timeout(self.connect_timeout));
+                try!(tcp.set_write_timeout(self.connect_timeout));
+                self.ssl.wrap_client(HttpStream(tcp), host).map(HttpsStream::Https)
+            }
+            _ => Err(hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                     format!("invalid scheme {}", scheme)))),
+        }
+    }
+}
+
+fn send_with(client: &Client, request: &Request) -> Result<Response> {
+    let method = match request.method {
+        Method::Get => HyperMethod::Get,
+        Method::Post => HyperMethod::Post,
+        Method::Delete => HyperMethod::Delete,
+    };
+
+    let mut headers = Headers::new();
+    for &(ref name, ref value) in request.headers.iter() {
+        headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+    }
+
+    let mut req = client
+        .request(method, &request.url[..])
+        .headers(headers);
+
+    if let Some(ref body) = request.body {
+        req = req.body(&body[..]);
+    }
+
+    let mut res = match req.send() {
+        Ok(res) => res,
+        Err(hyper::error::Error::Io(ref err)) if is_timeout(err) => return Err(StockfighterError::Timeout),
+        Err(err) => return Err(StockfighterError::Hyper(err)),
+    };
+
+    let mut body = String::new();
+    match res.read_to_string(&mut body) {
+        Ok(_) => {},
+        Err(ref err) if is_timeout(err) => return Err(StockfighterError::Timeout),
+        Err(err) => return Err(StockfighterError::Io(err)),
+    }
+
+    Ok(Response { status: res.status.to_u16(), body: body })
+}
+
+/// Records every request and answers them, in order, with responses queued by `push_response`
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use stockfighter::{Stockfighter, ScriptedTransport};
+///
+/// let transport = Arc::new(ScriptedTransport::new());
+/// transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+///
+/// let sf = Stockfighter::builder("fake api key").transport(transport.clone()).build().unwrap();
+/// assert!(sf.heartbeat().is_ok());
+/// assert_eq!(transport.requests()[0].url, "https://api.stockfighter.io/ob/api/heartbeat");
+/// ```
+pub struct ScriptedTransport {
+    requests: Mutex<Vec<Request>>,
+    responses: Mutex<VecDeque<Response>>,
+}
+
+impl ScriptedTransport {
+    pub fn new() -> ScriptedTransport {
+        ScriptedTransport {
+            requests: Mutex::new(Vec::new()),
+            responses: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Queue a response for the next unanswered request
+    pub fn push_response<S>(&self, status: u16, body: S) where S: Into<String> {
+        self.responses.lock().unwrap().push_back(Response::new(status, body));
+    }
+
+    /// Every request sent so far, oldest first
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Default for ScriptedTransport {
+    fn default() -> ScriptedTransport {
+        ScriptedTransport::new()
+    }
+}
+
+impl Transport for ScriptedTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        self.requests.lock().unwrap().push(request.clone());
+
+        match self.responses.lock().unwrap().pop_front() {
+            Some(response) => Ok(response),
+            None => Err(StockfighterError::Io(io::Error::new(io::ErrorKind::NotConnected,
+                                                             "no scripted response left"))),
+        }
+    }
+}
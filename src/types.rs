@@ -0,0 +1,233 @@
+//! Checked types for the arguments of `Stockfighter` and `Gamemaster` calls
+//!
+//! Every call takes its arguments through `IntoChecked`, so both the types below and plain
+//! `&str`/`usize` values are accepted. Plain values are validated when the call is made, while
+//! typed values catch swapped arguments at compile time.
+
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+use {Result, StockfighterError, Price};
+
+/// Conversion into a validated argument type
+pub trait IntoChecked<T> {
+    fn into_checked(self) -> Result<T>;
+}
+
+fn invalid<T>(what: &str, value: &str, rule: &str) -> Result<T> {
+    Err(StockfighterError::InvalidArgument(format!("{} {:?} {}", what, value, rule)))
+}
+
+fn is_code(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+fn is_level_name(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+macro_rules! code_type {
+    ($name:ident, $what:expr, $check:expr, $rule:expr) => {
+        #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn new<S>(s: S) -> Result<$name> where S: Into<String> {
+                let s = s.into();
+                if $check(&s) {
+                    Ok($name(s))
+                } else {
+                    invalid($what, &s, $rule)
+                }
+            }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = StockfighterError;
+
+            fn from_str(s: &str) -> Result<$name> {
+                $name::new(s)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> String {
+                value.0
+            }
+        }
+
+        impl IntoChecked<$name> for $name {
+            fn into_checked(self) -> Result<$name> {
+                Ok(self)
+            }
+        }
+
+        impl<'a> IntoChecked<$name> for &'a $name {
+            fn into_checked(self) -> Result<$name> {
+                Ok(self.clone())
+            }
+        }
+
+        impl<'a> IntoChecked<$name> for &'a str {
+            fn into_checked(self) -> Result<$name> {
+                $name::new(self)
+            }
+        }
+
+        impl IntoChecked<$name> for String {
+            fn into_checked(self) -> Result<$name> {
+                $name::new(self)
+            }
+        }
+
+        impl<'a> IntoChecked<$name> for &'a String {
+            fn into_checked(self) -> Result<$name> {
+                $name::new(&self[..])
+            }
+        }
+    }
+}
+
+macro_rules! number_type {
+    (@int $name:ident, $what:expr, $($int:ty)*) => {
+        $(
+            impl IntoChecked<$name> for $int {
+                #[allow(unused_comparisons)]
+                fn into_checked(self) -> Result<$name> {
+                    if self < 0 as $int || self as u64 > usize::max_value() as u64 {
+                        return invalid($what, &self.to_string(), "is out of range");
+                    }
+                    // Checked as a `usize` from here on, which `Price` takes as cents
+                    (self as usize).into_checked()
+                }
+            }
+        )*
+    };
+    ($name:ident, $what:expr, $check:expr, $rule:expr) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(usize);
+
+        impl $name {
+            pub fn new(n: usize) -> Result<$name> {
+                if $check(n) {
+                    Ok($name(n))
+                } else {
+                    invalid($what, &n.to_string(), $rule)
+                }
+            }
+
+            pub fn get(&self) -> usize {
+                self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = StockfighterError;
+
+            fn from_str(s: &str) -> Result<$name> {
+                match s.parse() {
+                    Ok(n) => $name::new(n),
+                    Err(_) => invalid($what, s, "is not a number"),
+                }
+            }
+        }
+
+        impl From<$name> for usize {
+            fn from(value: $name) -> usize {
+                value.0
+            }
+        }
+
+        impl IntoChecked<$name> for $name {
+            fn into_checked(self) -> Result<$name> {
+                Ok(self)
+            }
+        }
+
+        impl IntoChecked<$name> for usize {
+            fn into_checked(self) -> Result<$name> {
+                $name::new(self)
+            }
+        }
+
+        // Unsuffixed integer literals default to i32, so other integer types are accepted too
+        number_type!(@int $name, $what, u32 u64 i32 i64);
+    };
+}
+
+code_type!(Venue, "venue", is_code, "must be uppercase letters and digits, e.g. TESTEX");
+code_type!(Symbol, "symbol", is_code, "must be uppercase letters and digits, e.g. FOOBAR");
+code_type!(Account, "account",
+           |s: &str| is_code(s) && s.chars().next().map_or(false, |c| c.is_ascii_uppercase()),
+           "must be uppercase letters and digits starting with a letter, e.g. EXB123456");
+code_type!(Level, "level", is_level_name, "must be lowercase letters, digits and underscores, e.g. first_steps");
+
+number_type!(OrderId, "order id", |_| true, "");
+number_type!(Qty, "quantity", |n| n > 0, "must not be zero");
+number_type!(InstanceId, "instance id", |_| true, "");
+
+impl From<usize> for OrderId {
+    fn from(id: usize) -> OrderId {
+        OrderId(id)
+    }
+}
+
+impl From<usize> for InstanceId {
+    fn from(id: usize) -> InstanceId {
+        InstanceId(id)
+    }
+}
+
+impl IntoChecked<Price> for Price {
+    fn into_checked(self) -> Result<Price> {
+        Ok(self)
+    }
+}
+
+/// A plain number is a price in cents, the unit the API uses
+impl IntoChecked<Price> for usize {
+    fn into_checked(self) -> Result<Price> {
+        Ok(Price::from_cents(self))
+    }
+}
+
+/// A string is a price in dollars, e.g. `"$12.34"` or `"12.34"`. Unlike a number, `"50"` is
+/// $50.00 rather than 50 cents.
+impl<'a> IntoChecked<Price> for &'a str {
+    fn into_checked(self) -> Result<Price> {
+        self.parse()
+    }
+}
+
+number_type!(@int Price, "price", u32 u64 i32 i64);
@@ -0,0 +1,286 @@
+//! WebSocket connection helpers shared by the ticker tape and executions feeds
+
+use std::borrow::Cow;
+use std::io::{self, BufRead};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use openssl::ssl::{SslContext, SslMethod, SslStream};
+use websocket::{Client, Message};
+use websocket::message::Type;
+use websocket::Receiver as ReceiverTrait;
+use websocket::client::{Sender, Receiver};
+use websocket::client::request::{Request, Url};
+use websocket::dataframe::DataFrame;
+use websocket::result::WebSocketError;
+use websocket::stream::WebSocketStream;
+
+use {Result, StockfighterError};
+
+pub type Connection = Client<DataFrame, Sender<WebSocketStream>, Receiver<WebSocketStream>>;
+
+/// Open a WebSocket to `url`, giving up with `StockfighterError::Timeout` if the TCP connect, or
+/// any read or write of the handshake, takes longer than `timeout`
+pub fn connect(url: &str, timeout: Option<Duration>) -> Result<Connection> {
+    let wss = match Url::parse(url) {
+        Ok(wss) => wss,
+        Err(_) => return Err(StockfighterError::InvalidUrl(url.to_owned())),
+    };
+    let host = match wss.serialize_host() {
+        Some(host) => host,
+        None => return Err(StockfighterError::InvalidUrl(url.to_owned())),
+    };
+    let secure = wss.scheme == "wss";
+    let port = wss.port_or_default().unwrap_or(if secure { 443 } else { 80 });
+
+    // websocket 0.15 connects without a timeout, so the socket is set up here and handed to it
+    let tcp = match connect_tcp(&host, port, timeout) {
+        Ok(tcp) => tcp,
+        Err(ref err) if is_timeout(err) => return Err(StockfighterError::Timeout),
+        Err(err) => return Err(StockfighterError::Io(err)),
+    };
+    try!(tcp.set_read_timeout(timeout));
+    try!(tcp.set_write_timeout(timeout));
+    let handle = try!(tcp.try_clone());
+
+    match handshake(&wss, tcp, secure) {
+        Ok(connection) => {
+            // Feeds choose their own read timeouts from here on
+            try!(handle.set_read_timeout(None));
+            try!(handle.set_write_timeout(None));
+            Ok(connection)
+        }
+        Err(StockfighterError::Io(ref err)) if is_timeout(err) => Err(StockfighterError::Timeout),
+        Err(StockfighterError::WebSocket(WebSocketError::IoError(ref err))) if is_timeout(err) => {
+            Err(StockfighterError::Timeout)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn handshake(url: &Url, tcp: TcpStream, secure: bool) -> Result<Connection> {
+    let stream = if secure {
+        let context = try!(SslContext::new(SslMethod::Tlsv1).map_err(WebSocketError::from));
+        WebSocketStream::Ssl(try!(SslStream::connect(&context, tcp).map_err(WebSocketError::from)))
+    } else {
+        WebSocketStream::Tcp(tcp)
+    };
+
+    let request = try!(Request::new(url, try!(stream.try_clone()), stream));
+    let response = try!(request.send());
+    try!(response.validate());
+    Ok(response.begin())
+}
+
+/// Connect to `host`, trying each of its addresses in turn and giving each at most `timeout`.
+/// Looking up the addresses is not bounded by `timeout`.
+pub fn connect_tcp(host: &str, port: u16, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect((host, port)),
+    };
+
+    let mut last_err = None;
+    for addr in try!((host, port).to_socket_addrs()) {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(tcp) => return Ok(tcp),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", host))))
+}
+
+/// How long, in seconds, the rest of a message may take to arrive once its first bytes are in
+const FRAME_TIMEOUT: u64 = 5;
+
+/// Wait up to `wait` for a message. `Ok(None)` means nothing arrived in time.
+///
+/// Only the buffer is peeked at while waiting, so a timeout never leaves half a frame behind.
+pub fn poll(receiver: &mut Receiver<WebSocketStream>, wait: Duration) -> Result<Option<Message<'static>>> {
+    try!(tcp(receiver.get_ref().get_ref()).set_read_timeout(Some(wait)));
+
+    match receiver.get_mut().fill_buf() {
+        Ok(buf) if buf.is_empty() => {
+            return Err(StockfighterError::Io(io::Error::new(io::ErrorKind::UnexpectedEof, "websocket closed")));
+        }
+        Ok(_) => {},
+        Err(ref err) if is_timeout(err) => return Ok(None),
+        Err(err) => return Err(StockfighterError::Io(err)),
+    }
+
+    try!(tcp(receiver.get_ref().get_ref()).set_read_timeout(Some(Duration::from_secs(FRAME_TIMEOUT))));
+    let message: Message = try!(receiver.recv_message());
+    Ok(Some(message))
+}
+
+/// Largest frame a `FrameBuffer` accepts, in bytes. Feed messages are a few hundred bytes.
+const MAX_FRAME: u64 = 16 * 1024 * 1024;
+
+/// Reads whatever messages have arrived on a connection without ever waiting for more
+///
+/// The socket is switched to non-blocking mode, which also affects the `Sender` split off the
+/// same connection. Control frames and feed messages are small enough that a write to a
+/// non-blocking socket does not come back half done in practice.
+pub struct NonBlockingReceiver {
+    receiver: Receiver<WebSocketStream>,
+    frames: FrameBuffer,
+}
+
+impl NonBlockingReceiver {
+
+    pub fn new(receiver: Receiver<WebSocketStream>) -> Result<NonBlockingReceiver> {
+        try!(tcp(receiver.get_ref().get_ref()).set_nonblocking(true));
+        Ok(NonBlockingReceiver { receiver: receiver, frames: FrameBuffer::new() })
+    }
+
+    /// Every complete message that has arrived so far, possibly none. An error means the
+    /// connection is lost.
+    pub fn recv_available(&mut self) -> Result<Vec<Message<'static>>> {
+        loop {
+            let read = {
+                let reader = self.receiver.get_mut();
+                let read = match reader.fill_buf() {
+                    Ok(buf) if buf.is_empty() => {
+                        return Err(StockfighterError::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                                        "websocket closed")));
+                    }
+                    Ok(buf) => {
+                        self.frames.extend(buf);
+                        buf.len()
+                    }
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(StockfighterError::Io(err)),
+                };
+                reader.consume(read);
+                read
+            };
+            trace!("Read {} bytes from a non-blocking websocket", read);
+        }
+
+        let mut messages = Vec::new();
+        while let Some(message) = try!(self.frames.next_message()) {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
+
+/// Bytes read from a connection, cut into messages once their frames have fully arrived
+pub struct FrameBuffer {
+    buf: Vec<u8>,
+    // The opcode and payload so far of a message sent in several frames
+    fragments: Option<(u8, Vec<u8>)>,
+}
+
+impl FrameBuffer {
+
+    pub fn new() -> FrameBuffer {
+        FrameBuffer { buf: Vec::new(), fragments: None }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The next complete message, or `None` until more bytes arrive
+    pub fn next_message(&mut self) -> Result<Option<Message<'static>>> {
+        loop {
+            let (finished, opcode, payload) = match try!(self.next_frame()) {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            // Control frames may arrive in between the fragments of a message
+            if opcode >= 8 {
+                return message(opcode, payload).map(Some);
+            }
+
+            let (opcode, payload) = match (opcode, self.fragments.take()) {
+                (0, Some((opcode, mut so_far))) => {
+                    so_far.extend_from_slice(&payload);
+                    (opcode, so_far)
+                }
+                (0, None) => return Err(frame_error("continuation frame without a message to continue")),
+                (_, Some(_)) => return Err(frame_error("new message before the last one was finished")),
+                (opcode, None) => (opcode, payload),
+            };
+
+            if finished {
+                return message(opcode, payload).map(Some);
+            }
+            self.fragments = Some((opcode, payload));
+        }
+    }
+
+    // Take the frame at the front of the buffer if all of it has arrived: whether it finishes
+    // its message, its opcode and its unmasked payload
+    fn next_frame(&mut self) -> Result<Option<(bool, u8, Vec<u8>)>> {
+        let buf = &self.buf;
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+
+        let (len, mut header) = match buf[1] & 0x7f {
+            126 if buf.len() < 4 => return Ok(None),
+            126 => ((buf[2] as u64) << 8 | buf[3] as u64, 4),
+            127 if buf.len() < 10 => return Ok(None),
+            127 => (buf[2..10].iter().fold(0, |len, &byte| len << 8 | byte as u64), 10),
+            len => (len as u64, 2),
+        };
+        if len > MAX_FRAME {
+            return Err(frame_error("frame too large"));
+        }
+
+        let masked = buf[1] & 0x80 != 0;
+        if masked {
+            header += 4;
+        }
+        let end = header + len as usize;
+        if buf.len() < end {
+            return Ok(None);
+        }
+
+        let mut payload = buf[header..end].to_vec();
+        if masked {
+            let mask = &buf[header - 4..header];
+            for (i, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[i % 4];
+            }
+        }
+        let (finished, opcode) = (buf[0] & 0x80 != 0, buf[0] & 0x0f);
+
+        self.buf = self.buf.split_off(end);
+        Ok(Some((finished, opcode, payload)))
+    }
+}
+
+fn message(opcode: u8, payload: Vec<u8>) -> Result<Message<'static>> {
+    let (opcode, cd_status_code, payload) = match opcode {
+        1 => (Type::Text, None, payload),
+        2 => (Type::Binary, None, payload),
+        8 if payload.len() >= 2 => (Type::Close, Some((payload[0] as u16) << 8 | payload[1] as u16), payload[2..].to_vec()),
+        8 => (Type::Close, None, payload),
+        9 => (Type::Ping, None, payload),
+        10 => (Type::Pong, None, payload),
+        _ => return Err(frame_error("unknown opcode")),
+    };
+    Ok(Message { opcode: opcode, cd_status_code: cd_status_code, payload: Cow::Owned(payload) })
+}
+
+fn frame_error(message: &str) -> StockfighterError {
+    StockfighterError::Io(io::Error::new(io::ErrorKind::InvalidData, format!("bad websocket frame: {}", message)))
+}
+
+/// The socket under a plain or TLS WebSocket stream
+fn tcp(stream: &WebSocketStream) -> &TcpStream {
+    match *stream {
+        WebSocketStream::Tcp(ref stream) => stream,
+        WebSocketStream::Ssl(ref stream) => stream.get_ref(),
+    }
+}
+
+/// Whether a socket error means a read or write timeout expired
+pub fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::TimedOut || err.kind() == io::ErrorKind::WouldBlock
+}
@@ -0,0 +1,27 @@
+#![cfg(feature = "async")]
+
+extern crate futures;
+extern crate stockfighter;
+
+use std::sync::Arc;
+
+use futures::Future;
+use stockfighter::{AsyncStockfighter, Stockfighter, StockfighterError, ScriptedTransport, Price};
+
+#[test]
+fn test_async_quote() {
+    let transport = Arc::new(ScriptedTransport::new());
+    transport.push_response(200, r#"{"ok": true, "symbol": "FOOBAR", "venue": "TESTEX", "last": 5100}"#);
+    transport.push_response(404, r#"{"ok": false, "error": "No venue exists with the symbol INVALID"}"#);
+
+    let sf = Stockfighter::builder("").transport(transport.clone()).build().unwrap();
+    let sf = AsyncStockfighter::from_blocking(sf);
+
+    let quote = sf.quote("TESTEX", "FOOBAR").wait().unwrap();
+    assert_eq!(quote.last, Price::from_cents(5100));
+
+    match sf.quote("INVALID", "FOOBAR").wait() {
+        Err(StockfighterError::NotFound(_)) => {},
+        _ => panic!()
+    }
+}
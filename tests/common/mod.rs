@@ -0,0 +1,17 @@
+//! Fixtures shared by the integration tests
+
+use std::sync::Arc;
+
+use stockfighter::{Stockfighter, ScriptedTransport, RetryPolicy, RateLimiter};
+
+/// Client answered by a `ScriptedTransport`, with the given retry policy and rate limiter
+pub fn scripted(retry: RetryPolicy, limiter: Option<RateLimiter>) -> (Arc<ScriptedTransport>, Stockfighter) {
+    let transport = Arc::new(ScriptedTransport::new());
+    let mut builder = Stockfighter::builder("secret")
+        .transport(transport.clone())
+        .retry_policy(retry);
+    if let Some(limiter) = limiter {
+        builder = builder.rate_limiter(Arc::new(limiter));
+    }
+    (transport, builder.build().unwrap())
+}
@@ -0,0 +1,144 @@
+extern crate stockfighter;
+
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+use stockfighter::{Stockfighter, StockfighterError};
+
+const CONFIG: &'static str = r#"
+[default]
+api_key = "secret"
+account = "EXB123456"
+venue = "TESTEX"
+
+[local]
+api_key = "other secret"
+base_url = "http://localhost:8000/ob/api"
+ws_url = "ws://localhost:8001/ob/api/ws"
+
+[bad]
+api_key = "secret"
+venue = "testex"
+
+[bad_url]
+api_key = "secret"
+ws_url = "https://api.stockfighter.io/ob/api/ws"
+
+[staging]
+api_key = "secret"
+base_url = "http://staging.example.com/ob/api"
+gm_url = "http://staging.example.com/gm"
+allow_plaintext = true
+
+[plaintext]
+api_key = "secret"
+base_url = "http://staging.example.com/ob/api"
+"#;
+
+fn write_config(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("stockfighter-{}-{}.toml", name, std::process::id()));
+    File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+    path
+}
+
+#[test]
+fn test_from_config() {
+    let path = write_config("profiles", CONFIG);
+
+    let sf = Stockfighter::from_config(&path).unwrap();
+    assert_eq!(sf.default_account().map(|a| a.as_str()), Some("EXB123456"));
+    assert_eq!(sf.default_venue().map(|v| v.as_str()), Some("TESTEX"));
+
+    let sf = Stockfighter::from_config_profile(&path, "local").unwrap();
+    assert!(sf.default_account().is_none());
+
+    match Stockfighter::from_config_profile(&path, "bad") {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("testex")),
+        _ => panic!(),
+    }
+    match Stockfighter::from_config_profile(&path, "bad_url") {
+        Err(StockfighterError::Config(ref message)) => {
+            assert!(message.contains(&path.display().to_string()));
+            assert!(message.contains("https://api.stockfighter.io/ob/api/ws"));
+        }
+        _ => panic!(),
+    }
+    assert!(Stockfighter::from_config_profile(&path, "staging").is_ok());
+    match Stockfighter::from_config_profile(&path, "plaintext") {
+        Err(StockfighterError::Config(ref message)) => {
+            assert!(message.contains(&path.display().to_string()));
+            assert!(message.contains("allow_plaintext"));
+        }
+        _ => panic!(),
+    }
+    match Stockfighter::from_config_profile(&path, "missing") {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("[missing]")),
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_malformed_config() {
+    let path = write_config("malformed", "[default]\naccount = \"EXB123456\"\n");
+    match Stockfighter::from_config(&path) {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("api_key")),
+        _ => panic!(),
+    }
+
+    let path = write_config("not-toml", "api_key = ");
+    match Stockfighter::from_config(&path) {
+        Err(StockfighterError::Config(_)) => {},
+        _ => panic!(),
+    }
+
+    match Stockfighter::from_config("/nonexistent/stockfighter.toml") {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("cannot read")),
+        _ => panic!(),
+    }
+}
+
+// The only test that touches the environment, since tests run in parallel
+#[test]
+fn test_from_env() {
+    env::remove_var("STOCKFIGHTER_API_KEY");
+    match Stockfighter::from_env() {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("STOCKFIGHTER_API_KEY")),
+        _ => panic!(),
+    }
+
+    env::set_var("STOCKFIGHTER_API_KEY", "secret");
+    env::set_var("STOCKFIGHTER_VENUE", "TESTEX");
+    env::remove_var("STOCKFIGHTER_ACCOUNT");
+    let sf = Stockfighter::from_env().unwrap();
+    assert_eq!(sf.default_venue().map(|v| v.as_str()), Some("TESTEX"));
+    assert!(sf.default_account().is_none());
+
+    env::set_var("STOCKFIGHTER_BASE_URL", "api.stockfighter.io/ob/api");
+    match Stockfighter::from_env() {
+        Err(StockfighterError::Config(ref message)) => {
+            assert!(message.contains("environment"));
+            assert!(message.contains("api.stockfighter.io/ob/api"));
+        }
+        _ => panic!(),
+    }
+
+    env::set_var("STOCKFIGHTER_BASE_URL", "http://example.com/ob/api");
+    match Stockfighter::from_env() {
+        Err(StockfighterError::Config(ref message)) => {
+            assert!(message.contains("environment"));
+            assert!(message.contains("STOCKFIGHTER_ALLOW_PLAINTEXT"));
+        }
+        _ => panic!(),
+    }
+    env::set_var("STOCKFIGHTER_ALLOW_PLAINTEXT", "true");
+    assert!(Stockfighter::from_env().is_ok());
+    env::set_var("STOCKFIGHTER_ALLOW_PLAINTEXT", "maybe");
+    match Stockfighter::from_env() {
+        Err(StockfighterError::Config(ref message)) => assert!(message.contains("STOCKFIGHTER_ALLOW_PLAINTEXT")),
+        _ => panic!(),
+    }
+    env::remove_var("STOCKFIGHTER_ALLOW_PLAINTEXT");
+    env::remove_var("STOCKFIGHTER_BASE_URL");
+}
@@ -0,0 +1,193 @@
+extern crate stockfighter;
+
+mod common;
+
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+
+use stockfighter::{StockfighterError, RetryPolicy, Gamemaster};
+use stockfighter::{InstanceState, InstanceEvent, StreamEvent, LevelSession, RateLimiter, RateLimitMode};
+use stockfighter::transport::Method;
+
+use common::scripted;
+
+const LEVEL_INSTANCE: &'static str = r#"{
+    "ok": true, "account": "EXB123456", "instanceId": 1234,
+    "instructions": {"Instructions": "Buy 100 shares", "Order Types": "Limit orders only"},
+    "secondsPerTradingDay": 5, "tickers": ["FOOBAR"], "venues": ["TESTEX"],
+    "balances": {"USD": 0}
+}"#;
+
+#[test]
+fn test_start_level() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(200, LEVEL_INSTANCE);
+
+    let level = gm.start_level("first_steps").unwrap();
+    assert_eq!(level.instance_id, 1234);
+    assert_eq!(level.account, "EXB123456");
+    assert_eq!(level.venues, vec!["TESTEX".to_owned()]);
+    assert_eq!(level.tickers, vec!["FOOBAR".to_owned()]);
+    assert_eq!(level.seconds_per_trading_day, Some(5));
+    assert_eq!(level.balances.get("USD"), Some(&0));
+    assert_eq!(level.instructions.get("Order Types").map(|s| &s[..]), Some("Limit orders only"));
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].method, Method::Post);
+    assert_eq!(requests[0].url, "https://www.stockfighter.io/gm/levels/first_steps");
+    assert_eq!(requests[0].header("x-starfighter-authorization"), Some("secret"));
+    assert_eq!(requests[0].body, None);
+
+    assert!(gm.start_level("First Steps").is_err());
+}
+
+#[test]
+fn test_instance_requests() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(200, LEVEL_INSTANCE);
+    transport.push_response(200, LEVEL_INSTANCE);
+    transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+
+    assert_eq!(gm.restart(1234).unwrap().instance_id, 1234);
+    assert_eq!(gm.resume(1234).unwrap().instance_id, 1234);
+    assert!(gm.stop(1234).is_ok());
+
+    let urls: Vec<_> = transport.requests().into_iter().map(|request| request.url).collect();
+    assert_eq!(urls, vec!["https://www.stockfighter.io/gm/instances/1234/restart",
+                          "https://www.stockfighter.io/gm/instances/1234/resume",
+                          "https://www.stockfighter.io/gm/instances/1234/stop"]);
+}
+
+#[test]
+fn test_gm_errors() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(401, r#"{"ok": false, "error": "Not authorized"}"#);
+    transport.push_response(200, r#"{"ok": false, "error": "No such instance"}"#);
+
+    match gm.start_level("first_steps") {
+        Err(StockfighterError::Unauthorized(ref m)) if m == "Not authorized" => {},
+        _ => panic!()
+    }
+    match gm.resume(4321) {
+        Err(StockfighterError::Api { ref message, .. }) if message == "No such instance" => {},
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_gm_actions_are_not_retried_or_limited() {
+    let limiter = RateLimiter::new(RateLimitMode::Fail).orders(1, Duration::from_secs(60));
+    let retry = RetryPolicy::new().retry_new_orders(true).base_delay(Duration::from_millis(0));
+    let (transport, sf) = scripted(retry, Some(limiter));
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(503, "unavailable");
+    transport.push_response(200, LEVEL_INSTANCE);
+    transport.push_response(200, LEVEL_INSTANCE);
+
+    assert!(gm.start_level("first_steps").is_err());
+    assert_eq!(transport.requests().len(), 1);
+
+    // Neither the failed start nor these took from the order entry budget
+    assert!(gm.start_level("first_steps").is_ok());
+    assert!(gm.resume(1234).is_ok());
+    assert_eq!(transport.requests().len(), 3);
+}
+
+fn instance_status(day: u32, state: &str, flash: &str) -> String {
+    format!(r#"{{
+        "ok": true, "id": 1234, "done": {}, "state": "{}",
+        "details": {{"tradingDay": {}, "endOfTheWorldDay": 372}},
+        "flash": {}
+    }}"#, state != "open", state, day, flash)
+}
+
+#[test]
+fn test_instance_status() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(200, instance_status(12, "open", r#"{"info": "You have 12 days left"}"#));
+    transport.push_response(200, r#"{"ok": true, "id": 1234, "done": true, "state": "closed"}"#);
+
+    let status = gm.instance_status(1234).unwrap();
+    assert_eq!(status.state, InstanceState::Open);
+    assert!(!status.done);
+    assert_eq!(status.details.as_ref().map(|details| details.trading_day), Some(12));
+    assert_eq!(status.details.as_ref().map(|details| details.end_of_the_world_day), Some(372));
+    assert_eq!(status.flash.get("info").map(|s| &s[..]), Some("You have 12 days left"));
+
+    let status = gm.instance_status(1234).unwrap();
+    assert_eq!(status.state, InstanceState::Other("closed".to_owned()));
+    assert!(status.details.is_none() && status.flash.is_empty());
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].method, Method::Get);
+    assert_eq!(requests[0].url, "https://www.stockfighter.io/gm/instances/1234");
+}
+
+#[test]
+fn test_watch_instance() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    let gm = Gamemaster::new(&sf);
+    transport.push_response(200, instance_status(0, "open", r#"{"info": "Buy 100 shares"}"#));
+    transport.push_response(200, instance_status(0, "open", r#"{"info": "Buy 100 shares"}"#));
+    transport.push_response(500, "oops");
+    transport.push_response(200, instance_status(1, "open", r#"{"info": "Buy 100 shares"}"#));
+    transport.push_response(200, instance_status(2, "won", r#"{"success": "Level complete"}"#));
+
+    // Read with a timeout, so a subscription that fails to end fails the test instead of hanging it
+    let watch = gm.watch_instance(1234, Duration::from_millis(10)).unwrap();
+    let mut events = Vec::new();
+    loop {
+        match watch.recv_timeout(Duration::from_secs(5)) {
+            Ok(event) => events.push(event),
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => panic!("the subscription did not end after the level was won"),
+        }
+    }
+    assert_eq!(events.len(), 6);
+
+    match events[0] {
+        StreamEvent::Message(InstanceEvent::Flash { ref kind, ref message })
+            if kind == "info" && message == "Buy 100 shares" => {},
+        _ => panic!()
+    }
+    match events[1] {
+        StreamEvent::Error(StockfighterError::Api { status: 500, .. }) => {},
+        _ => panic!()
+    }
+    match events[2] {
+        StreamEvent::Message(InstanceEvent::TradingDay { day: 1, end_of_the_world_day: 372 }) => {},
+        _ => panic!()
+    }
+    match events[3] {
+        StreamEvent::Message(InstanceEvent::TradingDay { day: 2, .. }) => {},
+        _ => panic!()
+    }
+    match events[4] {
+        StreamEvent::Message(InstanceEvent::Flash { ref kind, .. }) if kind == "success" => {},
+        _ => panic!()
+    }
+    match events[5] {
+        StreamEvent::Message(InstanceEvent::Won(ref status)) => assert!(status.done),
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_session_stops_a_level_it_cannot_open() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, r#"{"ok": true, "account": "EXB123456", "instanceId": 1234, "venues": [], "tickers": []}"#);
+    transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+
+    match LevelSession::start(&sf, "first_steps") {
+        Err(StockfighterError::InvalidArgument(ref message)) if message == "the level has no venues" => {},
+        _ => panic!()
+    }
+
+    let urls: Vec<_> = transport.requests().into_iter().map(|request| request.url).collect();
+    assert_eq!(urls, vec!["https://www.stockfighter.io/gm/levels/first_steps",
+                          "https://www.stockfighter.io/gm/instances/1234/stop"]);
+}
@@ -1,8 +1,36 @@
 extern crate stockfighter;
 extern crate env_logger;
+extern crate websocket;
 
 use stockfighter::Stockfighter;
+use stockfighter::StockfighterBuilder;
 use stockfighter::StockfighterError;
+use stockfighter::MarketDataHub;
+use stockfighter::{RetryPolicy, StreamEvent};
+
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn test_builder_urls() {
+    assert!(StockfighterBuilder::new("").build().is_ok());
+    assert!(StockfighterBuilder::new("")
+        .base_url("http://localhost:8000/ob/api/")
+        .ws_url("ws://127.0.0.1:8001/ob/api/ws")
+        .build().is_ok());
+
+    match StockfighterBuilder::new("").base_url("http://staging.example.com/ob/api").build() {
+        Err(StockfighterError::InvalidUrl(ref s)) if s == "http://staging.example.com/ob/api" => {},
+        _ => panic!()
+    }
+    assert!(StockfighterBuilder::new("")
+        .base_url("http://staging.example.com/ob/api")
+        .allow_plaintext(true)
+        .build().is_ok());
+    assert!(StockfighterBuilder::new("").ws_url("https://api.stockfighter.io/ob/api/ws").build().is_err());
+    assert!(StockfighterBuilder::new("").base_url("not a url").build().is_err());
+    assert!(StockfighterBuilder::new("").gm_url("http://staging.example.com/gm").build().is_err());
+}
 
 #[test]
 fn test_heartbeat() {
@@ -45,11 +73,11 @@ fn test_orderbook_for_stock() {
     let sf = Stockfighter::new("");
     assert!(sf.orderbook_for_stock("TESTEX", "FOOBAR").is_ok());
     match sf.orderbook_for_stock("INVALID", "FOOBAR") {
-        Err(StockfighterError::ApiError) => {},
+        Err(StockfighterError::NotFound(_)) => {},
         _ => panic!()
     }
     match sf.orderbook_for_stock("TESTEX", "INVALID") {
-        Err(StockfighterError::ApiError) => {},
+        Err(StockfighterError::NotFound(_)) => {},
         _ => panic!()
     }
 }
@@ -67,7 +95,10 @@ fn test_status_for_all_orders() {
     // TODO Create an is_ok test when we figure out how to test this without an API key.
     // As of now an is_ok test will pass with an existing order and an API key.
     let sf = Stockfighter::new("");
-    assert!(sf.status_for_all_orders("TESTEX", "BA12DFEI12").is_err());
+    match sf.status_for_all_orders("TESTEX", "BA12DFEI12") {
+        Err(StockfighterError::Unauthorized(_)) => {},
+        _ => panic!()
+    }
 }
 
 #[test]
@@ -79,41 +110,139 @@ fn test_status_for_all_orders_on_a_stock() {
 }
 
 #[test]
-#[ignore] // this test will block forever
 fn test_ticker_tape_venue_with() {
     let _ = env_logger::init();
 
     let sf = Stockfighter::new("");
-    let handle = sf.ticker_tape_venue_with("EXB123456", "TESTEX", |quote| println!("{:?}", quote));
-    let _ = handle.unwrap().join();
+    let handle = sf.ticker_tape_venue_with("EXB123456", "TESTEX", |quote| println!("{:?}", quote)).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(handle.is_running());
+    handle.stop();
 }
 
 #[test]
-#[ignore] // this test will block forever
 fn test_ticker_tape_venue_stock_with() {
     let _ = env_logger::init();
 
     let sf = Stockfighter::new("");
     let handle = sf.ticker_tape_venue_stock_with("EXB123456", "TESTEX", "FOOBAR", |quote| println!("{:?}", quote));
-    let _ = handle.unwrap().join();
+    thread::sleep(Duration::from_millis(500));
+    handle.unwrap().stop();
 }
 
 #[test]
-#[ignore] // this test will block forever
 fn test_executions_venue_with() {
     let _ = env_logger::init();
 
     let sf = Stockfighter::new("");
     let handle = sf.executions_venue_with("EXB123456", "TESTEX", |quote| println!("{:?}", quote));
-    let _ = handle.unwrap().join();
+    thread::sleep(Duration::from_millis(500));
+    handle.unwrap().stop();
 }
 
 #[test]
-#[ignore] // this test will block forever
 fn test_executions_venue_stock_with() {
     let _ = env_logger::init();
 
     let sf = Stockfighter::new("");
     let handle = sf.executions_venue_stock_with("EXB123456", "TESTEX", "FOOBAR", |quote| println!("{:?}", quote));
-    let _ = handle.unwrap().join();
+    thread::sleep(Duration::from_millis(500));
+    // Dropping the handle stops the subscription too
+    drop(handle.unwrap());
+}
+
+#[test]
+fn test_ticker_tape_venue() {
+    let _ = env_logger::init();
+
+    let sf = Stockfighter::new("");
+    let quotes = sf.ticker_tape_venue("EXB123456", "TESTEX").unwrap();
+    for _ in 0..5 {
+        if let Ok(event) = quotes.recv_timeout(Duration::from_millis(100)) {
+            println!("{:?}", event);
+        }
+    }
+    quotes.stop();
+}
+
+#[test]
+fn test_executions_venue_stock() {
+    let _ = env_logger::init();
+
+    let sf = Stockfighter::new("");
+    let executions = sf.executions_venue_stock("EXB123456", "TESTEX", "FOOBAR").unwrap();
+    if let Ok(event) = executions.recv_timeout(Duration::from_millis(500)) {
+        println!("{:?}", event);
+    }
+    executions.stop();
+}
+
+#[test]
+fn test_executions_venue_with_state() {
+    let _ = env_logger::init();
+
+    let sf = Stockfighter::new("");
+    let handle = sf.executions_venue_with_state("EXB123456", "TESTEX", 0, |events, _| *events += 1).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(handle.stop().is_some());
+}
+
+#[test]
+fn test_subscription_ends_when_the_feed_gives_up() {
+    let _ = env_logger::init();
+
+    // Accepts one connection and closes it, then refuses any reconnect
+    let server = websocket::Server::bind("127.0.0.1:0").unwrap();
+    let ws_url = format!("ws://{}", server.local_addr().unwrap());
+    let accepting = thread::spawn(move || {
+        let connection = server.accept().unwrap();
+        drop(server);
+        let request = connection.read_request().unwrap();
+        drop(request.accept().send().unwrap());
+    });
+
+    let sf = StockfighterBuilder::new("")
+        .ws_url(ws_url)
+        .connect_timeout(Some(Duration::from_secs(1)))
+        .reconnect_policy(RetryPolicy::new().max_attempts(2).base_delay(Duration::from_millis(10)))
+        .build().unwrap();
+    let quotes = sf.ticker_tape_venue("EXB123456", "TESTEX").unwrap();
+    accepting.join().unwrap();
+
+    // Only ends if the feed thread let go of its sender
+    let events: Vec<_> = quotes.collect();
+    assert!(events.iter().any(|event| match *event { StreamEvent::Disconnected => true, _ => false }));
+}
+
+#[test]
+fn test_market_data_hub() {
+    let _ = env_logger::init();
+
+    let sf = Stockfighter::new("");
+    let hub = MarketDataHub::new(&sf);
+    let first = hub.ticker_tape_venue_stock("EXB123456", "TESTEX", "FOOBAR").unwrap();
+    let second = hub.ticker_tape_venue_stock("EXB123456", "TESTEX", "FOOBAR").unwrap();
+    let executions = hub.executions_venue("EXB123456", "TESTEX").unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(hub.feed_count(), 2);
+
+    first.stop();
+    executions.stop();
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(hub.feed_count(), 1);
+    drop(second);
+}
+
+#[test]
+fn test_keepalive_ping() {
+    let _ = env_logger::init();
+
+    let sf = StockfighterBuilder::new("")
+        .ping_interval(Some(Duration::from_millis(200)))
+        .stale_after(Some(Duration::from_secs(5)))
+        .build().unwrap();
+    let quotes = sf.ticker_tape_venue("EXB123456", "TESTEX").unwrap();
+    thread::sleep(Duration::from_millis(1000));
+    assert!(quotes.ping_rtt().is_some());
+    quotes.stop();
 }
@@ -0,0 +1,105 @@
+extern crate stockfighter;
+extern crate serde_json;
+extern crate chrono;
+
+use stockfighter::{ExecutionOrdersStatuses, OrderDirection, OrderType, Price, Quote, Timestamp};
+
+const EXECUTION: &'static str = r#"{
+    "ok": true,
+    "account": "EXB123456",
+    "venue": "TESTEX",
+    "symbol": "FOOBAR",
+    "order": {
+        "ok": true, "symbol": "FOOBAR", "venue": "TESTEX", "direction": "sell",
+        "originalQty": 100, "qty": 0, "price": 5000, "orderType": "limit", "id": 1000,
+        "account": "EXB123456", "ts": "2015-12-03T18:45:25.389409883Z",
+        "fills": [{"price": 5000, "qty": 100, "ts": "2015-12-03T18:45:25.389409883Z"}],
+        "totalFilled": 100, "open": false
+    },
+    "standingId": 1000,
+    "incomingId": 1001,
+    "price": 5000,
+    "filled": 100,
+    "filledAt": "2015-12-03T18:45:25.389409883Z",
+    "standingComplete": true,
+    "incomingComplete": false
+}"#;
+
+#[test]
+fn test_decode_execution() {
+    let execution: ExecutionOrdersStatuses = serde_json::from_str(EXECUTION).unwrap();
+    assert_eq!(execution.standing_id, 1000);
+    assert_eq!(execution.incoming_id, 1001);
+    assert!(execution.standing_complete);
+    assert_eq!(execution.order.original_qty, Some(100));
+    assert_eq!(execution.order.total_filled, Some(100));
+    assert_eq!(execution.order.order_type, Some(OrderType::Limit));
+    assert_eq!(execution.filled_at.timestamp_subsec_nanos(), 389409883);
+    assert_eq!(execution.filled_at.raw(), "2015-12-03T18:45:25.389409883Z");
+    assert_eq!(execution.order.ts.as_ref(), Some(&execution.filled_at));
+    match execution.order.direction {
+        Some(OrderDirection::Sell) => {},
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_decode_quote() {
+    let quote: Quote = serde_json::from_str(r#"{
+        "ok": true, "symbol": "FOOBAR", "venue": "TESTEX", "bid": 5100, "ask": 5125,
+        "bidSize": 392, "askSize": 711, "bidDepth": 2748, "askDepth": 2237, "last": 5125,
+        "lastSize": 52, "lastTrade": "2015-07-13T05:38:17.33640392Z",
+        "quoteTime": "2015-07-13T05:38:17.33640392Z"
+    }"#).unwrap();
+    assert_eq!(quote.bid, Some(Price::from_cents(5100)));
+    assert_eq!(quote.last.to_string(), "$51.25");
+    assert_eq!(quote.bid_size, Some(392));
+    assert_eq!(quote.ask_depth, Some(2237));
+    assert_eq!(quote.last_size, Some(52));
+
+    let quote_time = quote.quote_time.unwrap();
+    assert_eq!(quote_time.timestamp_subsec_nanos(), 336403920);
+    assert!(quote_time.age() > chrono::Duration::zero());
+    assert_eq!(quote_time.since(&quote.last_trade.unwrap()), chrono::Duration::zero());
+}
+
+#[test]
+fn test_encode_order_type() {
+    assert_eq!(serde_json::to_string(&OrderType::FillOrKill).unwrap(), r#""fill-or-kill""#);
+    assert_eq!(serde_json::to_string(&OrderType::ImmediateOrCancel).unwrap(), r#""immediate-or-cancel""#);
+    assert_eq!(serde_json::to_string(&OrderDirection::Buy).unwrap(), r#""buy""#);
+}
+
+#[test]
+fn test_unknown_order_type() {
+    let order_type: OrderType = serde_json::from_str(r#""good-till-cancelled""#).unwrap();
+    assert_eq!(order_type, OrderType::Other("good-till-cancelled".to_owned()));
+    assert_eq!(serde_json::to_string(&order_type).unwrap(), r#""good-till-cancelled""#);
+    assert_eq!(OrderType::from("market"), OrderType::Market);
+}
+
+#[test]
+fn test_invalid_timestamp() {
+    assert!("yesterday".parse::<Timestamp>().is_err());
+    assert!(serde_json::from_str::<Timestamp>(r#""2015-13-45T99:00:00Z""#).is_err());
+}
+
+#[test]
+fn test_price() {
+    assert_eq!("$12.34".parse::<Price>().unwrap(), Price::from_cents(1234));
+    assert_eq!("12.3".parse::<Price>().unwrap(), Price::from_cents(1230));
+    assert_eq!("12".parse::<Price>().unwrap(), Price::from_cents(1200));
+    assert!("12.345".parse::<Price>().is_err());
+    assert!("-1.00".parse::<Price>().is_err());
+    assert!("$".parse::<Price>().is_err());
+    assert_eq!(Price::from_cents(5).to_string(), "$0.05");
+
+    let price = Price::from_cents(10000);
+    assert_eq!(price.checked_sub(Price::from_cents(10001)), None);
+    assert_eq!(price.offset_bps(25), Some(Price::from_cents(10025)));
+    assert_eq!(price.offset_bps(-10000), Some(Price::from_cents(0)));
+    assert_eq!(price.offset_bps(-10001), None);
+    assert_eq!(Price::from_cents(1237).round_down_to_tick(Price::from_cents(5)), Price::from_cents(1235));
+    assert_eq!(Price::from_cents(1236).round_up_to_tick(Price::from_cents(5)), Some(Price::from_cents(1240)));
+    assert_eq!(serde_json::to_string(&price).unwrap(), "10000");
+}
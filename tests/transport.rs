@@ -0,0 +1,317 @@
+extern crate stockfighter;
+
+mod common;
+
+use std::io::Read;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use stockfighter::{Stockfighter, StockfighterError, RetryPolicy, OrderDirection, OrderType};
+use stockfighter::{RateLimiter, RateLimitMode, HyperTransport, Transport};
+use stockfighter::{Venue, Symbol, Account, OrderId, Qty};
+use stockfighter::transport::{Method, Request};
+
+use common::scripted;
+
+// Retries without waiting in between
+fn quick_retry() -> RetryPolicy {
+    RetryPolicy::new().base_delay(Duration::from_millis(0))
+}
+
+const ORDER_STATUS: &'static str = r#"{
+    "ok": true, "symbol": "FOOBAR", "venue": "TESTEX", "direction": "buy",
+    "originalQty": 42, "qty": 42, "price": 10000, "orderType": "limit", "id": 1234,
+    "account": "EXB123456", "ts": "2015-07-05T22:16:18+00:00", "fills": [],
+    "totalFilled": 0, "open": true
+}"#;
+
+#[test]
+fn test_new_order_request() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, ORDER_STATUS);
+
+    let status = sf.new_order("EXB123456", "TESTEX", "FOOBAR", 10000, 42,
+                              OrderDirection::Buy, OrderType::Limit).unwrap();
+    assert_eq!(status.id, Some(1234));
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, Method::Post);
+    assert_eq!(requests[0].url, "https://api.stockfighter.io/ob/api/venues/TESTEX/stocks/FOOBAR/orders");
+    assert_eq!(requests[0].header("x-starfighter-authorization"), Some("secret"));
+    assert_eq!(requests[0].body.as_ref().map(|b| &b[..]),
+               Some(r#"{"account":"EXB123456","venue":"TESTEX","stock":"FOOBAR","price":10000,"qty":42,"direction":"buy","orderType":"limit"}"#));
+}
+
+#[test]
+fn test_cancel_an_order_request() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, ORDER_STATUS);
+
+    assert!(sf.cancel_an_order("TESTEX", "FOOBAR", 1234).is_ok());
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].method, Method::Delete);
+    assert_eq!(requests[0].url, "https://api.stockfighter.io/ob/api/venues/TESTEX/stocks/FOOBAR/orders/1234");
+    assert_eq!(requests[0].body, None);
+}
+
+#[test]
+fn test_canned_errors() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(404, r#"{"ok": false, "error": "No venue exists with the symbol INVALID"}"#);
+    transport.push_response(401, r#"{"ok": false, "error": "Not authorized"}"#);
+    transport.push_response(500, "oops");
+
+    match sf.quote("INVALID", "FOOBAR") {
+        Err(StockfighterError::NotFound(ref m)) if m == "No venue exists with the symbol INVALID" => {},
+        _ => panic!()
+    }
+    match sf.cancel_an_order("TESTEX", "FOOBAR", 1234) {
+        Err(StockfighterError::Unauthorized(ref m)) if m == "Not authorized" => {},
+        _ => panic!()
+    }
+    match sf.orderbook_for_stock("TESTEX", "FOOBAR") {
+        Err(StockfighterError::Api { status: 500, ref endpoint, ref message })
+            if endpoint == "/venues/TESTEX/stocks/FOOBAR" && message == "oops" => {},
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_heartbeat_not_ok() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, r#"{"ok": false, "error": "Scheduled maintenance"}"#);
+
+    match sf.heartbeat() {
+        Err(StockfighterError::ApiDown(ref message)) if message == "Scheduled maintenance" => {},
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_heartbeat_server_error() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(503, r#"{"ok": false, "error": "Database unreachable"}"#);
+
+    match sf.heartbeat() {
+        Err(StockfighterError::ApiDown(ref message)) if message == "Database unreachable" => {},
+        _ => panic!()
+    }
+}
+
+#[test]
+fn test_reads_are_retried() {
+    let (transport, sf) = scripted(quick_retry().max_attempts(3), None);
+    transport.push_response(502, "bad gateway");
+    transport.push_response(503, "unavailable");
+    transport.push_response(200, ORDER_STATUS);
+
+    assert!(sf.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn test_retry_gives_up() {
+    let (transport, sf) = scripted(quick_retry().max_attempts(2), None);
+    transport.push_response(500, "oops");
+    transport.push_response(500, "oops");
+    transport.push_response(404, r#"{"ok": false, "error": "not found"}"#);
+
+    assert!(sf.quote("TESTEX", "FOOBAR").is_err());
+    assert_eq!(transport.requests().len(), 2);
+
+    // Client errors are not transient
+    assert!(sf.quote("TESTEX", "FOOBAR").is_err());
+    assert_eq!(transport.requests().len(), 3);
+}
+
+#[test]
+fn test_new_orders_are_not_retried_by_default() {
+    let (transport, sf) = scripted(quick_retry(), None);
+    transport.push_response(500, "oops");
+    transport.push_response(200, ORDER_STATUS);
+
+    assert!(sf.new_order("EXB123456", "TESTEX", "FOOBAR", 10000, 42,
+                         OrderDirection::Buy, OrderType::Limit).is_err());
+    assert_eq!(transport.requests().len(), 1);
+
+    let (transport, sf) = scripted(quick_retry().retry_new_orders(true), None);
+    transport.push_response(500, "oops");
+    transport.push_response(200, ORDER_STATUS);
+
+    assert!(sf.new_order("EXB123456", "TESTEX", "FOOBAR", 10000, 42,
+                         OrderDirection::Buy, OrderType::Limit).is_ok());
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn test_rate_limit_fail() {
+    let limiter = RateLimiter::new(RateLimitMode::Fail)
+        .reads(1, Duration::from_secs(60))
+        .orders(1, Duration::from_secs(60));
+    let (transport, sf) = scripted(RetryPolicy::none(), Some(limiter));
+    for _ in 0..3 {
+        transport.push_response(200, ORDER_STATUS);
+    }
+
+    assert!(sf.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+    match sf.existing_order_status(1234, "TESTEX", "FOOBAR") {
+        Err(StockfighterError::RateLimited) => {},
+        _ => panic!()
+    }
+    // Order entry has its own budget
+    assert!(sf.cancel_an_order("TESTEX", "FOOBAR", 1234).is_ok());
+    assert_eq!(transport.requests().len(), 2);
+}
+
+#[test]
+fn test_rate_limit_block() {
+    let limiter = RateLimiter::new(RateLimitMode::Block).reads(1, Duration::from_millis(50));
+    let (transport, sf) = scripted(RetryPolicy::none(), Some(limiter));
+    transport.push_response(200, ORDER_STATUS);
+    transport.push_response(200, ORDER_STATUS);
+
+    let start = Instant::now();
+    assert!(sf.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+    assert!(sf.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+    assert!(start.elapsed() >= Duration::from_millis(40));
+}
+
+#[test]
+fn test_deadline() {
+    let (transport, sf) = scripted(quick_retry(), None);
+    transport.push_response(200, ORDER_STATUS);
+
+    let sf_deadline = sf.with_deadline(Duration::from_millis(100));
+    assert!(sf_deadline.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+    assert!(transport.requests()[0].timeout.unwrap() <= Duration::from_millis(100));
+
+    // A retry that would only start after the deadline is not attempted
+    let (transport, sf) = scripted(RetryPolicy::new().base_delay(Duration::from_secs(5)).jitter(0.0), None);
+    let sf = sf.with_deadline(Duration::from_millis(100));
+    transport.push_response(503, "unavailable");
+    transport.push_response(200, ORDER_STATUS);
+
+    match sf.existing_order_status(1234, "TESTEX", "FOOBAR") {
+        Err(StockfighterError::Timeout) => {},
+        _ => panic!()
+    }
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[test]
+fn test_deadline_with_rate_limit() {
+    let limiter = RateLimiter::new(RateLimitMode::Block).reads(1, Duration::from_secs(5));
+    let (transport, sf) = scripted(RetryPolicy::none(), Some(limiter));
+    let sf = sf.with_deadline(Duration::from_millis(100));
+    transport.push_response(200, ORDER_STATUS);
+    transport.push_response(200, ORDER_STATUS);
+
+    assert!(sf.existing_order_status(1234, "TESTEX", "FOOBAR").is_ok());
+
+    // Waiting for the next token would take us past the deadline
+    let start = Instant::now();
+    match sf.existing_order_status(1234, "TESTEX", "FOOBAR") {
+        Err(StockfighterError::Timeout) => {},
+        _ => panic!()
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(transport.requests().len(), 1);
+}
+
+#[test]
+fn test_invalid_arguments_are_not_sent() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+
+    match sf.quote("testex", "FOOBAR") {
+        Err(StockfighterError::InvalidArgument(_)) => {},
+        _ => panic!()
+    }
+    assert!(sf.new_order("EXB123456", "TESTEX", "FOOBAR", 10000, 0,
+                         OrderDirection::Buy, OrderType::Limit).is_err());
+    assert!(sf.new_order("EXB123456", "TESTEX", "FOOBAR", -1, 10,
+                         OrderDirection::Buy, OrderType::Limit).is_err());
+    assert!(sf.status_for_all_orders("TESTEX", "123456").is_err());
+    assert!(transport.requests().is_empty());
+}
+
+#[test]
+fn test_typed_arguments() {
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, r#"{"ok": true, "venue": "TESTEX", "orders": []}"#);
+
+    let venue: Venue = "TESTEX".parse().unwrap();
+    let account = Account::new("EXB123456").unwrap();
+    assert!(sf.status_for_all_orders(&venue, &account).is_ok());
+    assert_eq!(transport.requests()[0].url,
+               "https://api.stockfighter.io/ob/api/venues/TESTEX/accounts/EXB123456/orders");
+
+    assert!("FOO BAR".parse::<Symbol>().is_err());
+    assert!(Qty::new(0).is_err());
+    assert_eq!(String::from(venue), "TESTEX");
+    assert_eq!(usize::from(OrderId::from(1234)), 1234);
+}
+
+#[test]
+fn test_clones_share_the_client() {
+    fn assert_send_sync<T: Send + Sync + Clone>(_: &T) {}
+
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    assert_send_sync(&sf);
+    transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+    transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+
+    let clone = sf.clone();
+    thread::spawn(move || clone.heartbeat().unwrap()).join().unwrap();
+    assert!(sf.heartbeat().is_ok());
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].header("x-starfighter-authorization"), Some("secret"));
+}
+
+#[test]
+fn test_debug_hides_the_api_key() {
+    let sf = Stockfighter::builder("0123456789abcdef").log_requests(true).build().unwrap();
+    let debug = format!("{:?}", sf);
+    assert!(!debug.contains("0123456789abcdef"));
+    assert!(debug.contains("****cdef"));
+
+    let (transport, sf) = scripted(RetryPolicy::none(), None);
+    transport.push_response(200, r#"{"ok": true, "error": ""}"#);
+    sf.heartbeat().unwrap();
+
+    let request = &transport.requests()[0];
+    assert!(!format!("{:?}", request).contains("secret"));
+    assert_eq!(request.redacted_headers(), vec![("X-Starfighter-Authorization", "<redacted>")]);
+}
+
+#[test]
+fn test_timed_out_request_leaves_nothing_behind() {
+    // Accepts the connection but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let transport = HyperTransport::with_timeouts(Some(Duration::from_secs(1)), Some(Duration::from_secs(30)));
+    let request = Request {
+        method: Method::Get,
+        url: format!("http://{}/ob/api/heartbeat", listener.local_addr().unwrap()),
+        headers: Arc::new(Vec::new()),
+        body: None,
+        timeout: Some(Duration::from_millis(200)),
+    };
+
+    match transport.send(&request) {
+        Err(StockfighterError::Timeout) => (),
+        other => panic!("Expected a timeout, got {:?}", other),
+    }
+
+    // Nothing is left waiting on the socket, so the server sees it closed straight away rather
+    // than after the 30 second read timeout
+    let (mut connection, _) = listener.accept().unwrap();
+    connection.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    let mut received = Vec::new();
+    connection.read_to_end(&mut received).unwrap();
+    assert!(received.starts_with(b"GET /ob/api/heartbeat"));
+}